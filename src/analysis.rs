@@ -0,0 +1,115 @@
+use crate::*;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum FindingCategory {
+    /// Phrases with identical steps.
+    DuplicatePhrases,
+    /// Instruments that are identical apart from their number.
+    DuplicateInstruments,
+    /// A non-empty phrase that no chain uses.
+    UnusedPhrase,
+    /// A non-empty chain that isn't in the song.
+    UnusedChain,
+    /// An instrument that no phrase step plays.
+    UnusedInstrument,
+}
+
+/// One thing [`Song::analysis`] found. `indices` are slot numbers: every member
+/// of a group of duplicates, in ascending order, or the single unused slot.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Finding {
+    pub category: FindingCategory,
+    pub indices: Vec<usize>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SongAnalysis {
+    pub findings: Vec<Finding>,
+}
+
+impl SongAnalysis {
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn of_category(&self, category: FindingCategory) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(move |f| f.category == category)
+    }
+}
+
+/// Group the slots for which `keep` holds by `same`, returning groups of two or more.
+fn duplicates<T>(
+    slots: &[T],
+    keep: impl Fn(&T) -> bool,
+    same: impl Fn(&T, &T) -> bool,
+) -> Vec<Vec<usize>> {
+    let mut grouped = vec![false; slots.len()];
+    let mut groups = vec![];
+    for i in 0..slots.len() {
+        if grouped[i] || !keep(&slots[i]) {
+            continue;
+        }
+        let mut group = vec![i];
+        for j in i + 1..slots.len() {
+            if !grouped[j] && keep(&slots[j]) && same(&slots[i], &slots[j]) {
+                grouped[j] = true;
+                group.push(j);
+            }
+        }
+        if group.len() > 1 {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+impl Song {
+    /// Duplicate and unused slots, in the order of [`FindingCategory`]. Nothing is
+    /// modified, and the cost is a few comparisons per slot.
+    pub fn analysis(&self) -> SongAnalysis {
+        let mut findings = vec![];
+        let mut push = |category, indices| findings.push(Finding { category, indices });
+
+        for group in duplicates(&self.phrases, |p| !p.is_empty(), |a, b| a.steps == b.steps) {
+            push(FindingCategory::DuplicatePhrases, group);
+        }
+        let same_instrument = |a: &Instrument, b: &Instrument| {
+            let mut a = a.clone();
+            a.set_number(b.number().unwrap_or(0));
+            a == *b
+        };
+        for group in duplicates(
+            &self.instruments,
+            |i| *i != Instrument::None,
+            same_instrument,
+        ) {
+            push(FindingCategory::DuplicateInstruments, group);
+        }
+
+        for (i, phrase) in self.phrases.iter().enumerate() {
+            if !phrase.is_empty() && !self.phrase_is_referenced(i) {
+                push(FindingCategory::UnusedPhrase, vec![i]);
+            }
+        }
+        for (i, chain) in self.chains.iter().enumerate() {
+            if !chain.is_empty() && !self.song.steps.contains(&(i as u8)) {
+                push(FindingCategory::UnusedChain, vec![i]);
+            }
+        }
+        let mut played = [false; Self::N_INSTRUMENTS];
+        for step in self.phrases.iter().flat_map(|p| p.steps.iter()) {
+            if let Some(p) = played.get_mut(step.instrument as usize) {
+                *p = true;
+            }
+        }
+        for (i, instrument) in self.instruments.iter().enumerate() {
+            if *instrument != Instrument::None && !played[i] {
+                push(FindingCategory::UnusedInstrument, vec![i]);
+            }
+        }
+        SongAnalysis { findings }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::*;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A part of a song file, in file order.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SongSection {
+    /// The version, directory, transpose, tempo, quantize, name and key.
+    Header,
+    MidiSettings,
+    MixerSettings,
+    Grooves,
+    SongSteps,
+    Phrases,
+    Chains,
+    Tables,
+    Instruments,
+    EffectsSettings,
+    MidiMappings,
+    /// Firmware 2.5+.
+    Scales,
+}
+
+/// Which sections of a song [`Song::write`] stores, and which it leaves as
+/// they are in the buffer. A dropped section survives when writing over the
+/// file the song was read from, but edits to it are lost, and a template
+/// holds it empty.
+#[derive(PartialEq, Debug, Clone)]
+pub struct WriteCoverage {
+    pub written: Vec<SongSection>,
+    pub dropped: Vec<SongSection>,
+}
+
+impl WriteCoverage {
+    /// Whether every section of the song is written.
+    pub fn is_lossless(&self) -> bool {
+        self.dropped.is_empty()
+    }
+
+    pub fn writes(&self, section: SongSection) -> bool {
+        self.written.contains(&section)
+    }
+}
+
+impl Song {
+    /// What [`Song::write`] stores for this song's version, so that tools can
+    /// tell users what saving won't preserve.
+    pub fn write_coverage(&self) -> WriteCoverage {
+        use SongSection::*;
+        let mut written = vec![
+            Header,
+            MidiSettings,
+            MixerSettings,
+            Grooves,
+            SongSteps,
+            Phrases,
+            Chains,
+            Tables,
+            Instruments,
+            EffectsSettings,
+            MidiMappings,
+        ];
+        if self.version.at_least(2, 5) {
+            written.push(Scales);
+        }
+        WriteCoverage {
+            written,
+            dropped: vec![],
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::*;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+const PHRASE_HEADER: &str =
+    "row,note,velocity,instrument,fx1,fx1_value,fx2,fx2_value,fx3,fx3_value";
+const SONG_HEADER: &str =
+    "phrase,row,note,velocity,instrument,fx1,fx1_value,fx2,fx2_value,fx3,fx3_value";
+
+fn hex_cell(v: Option<u8>) -> String {
+    v.map(|v| format!("{:02x}", v)).unwrap_or_default()
+}
+
+fn parse_hex(cell: &str, what: &str) -> core::result::Result<Option<u8>, String> {
+    if cell.is_empty() {
+        return Ok(None);
+    }
+    u8::from_str_radix(cell, 16)
+        .map(Some)
+        .map_err(|_| format!("Invalid {} {:?}", what, cell))
+}
+
+impl Phrase {
+    /// The phrase as CSV, one line per step after a header:
+    ///
+    /// `row,note,velocity,instrument,fx1,fx1_value,fx2,fx2_value,fx3,fx3_value`
+    ///
+    /// `row` is the step number (`0`-`f`). Notes are written as the M8 shows them
+    /// (`C-4`, `F#5`, `OFF`). Velocities, instruments and FX values are 2 digit
+    /// hex, and FX commands are their mnemonics (e.g. `DEL`), or their hex byte
+    /// if this crate doesn't know them. Empty fields are blank cells, and an
+    /// empty FX has a blank command and value.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(PHRASE_HEADER);
+        csv.push('\n');
+        for (i, row) in self.rows().iter().enumerate() {
+            csv += &format!("{:x},{}\n", i, row_cells(row));
+        }
+        csv
+    }
+
+    /// Parse the CSV written by `to_csv` into phrase `number`. The header and all
+    /// 16 rows, in order, are required.
+    pub fn from_csv(number: u8, version: Version, csv: &str) -> core::result::Result<Self, String> {
+        let mut lines = csv.lines().filter(|l| !l.trim().is_empty());
+        if lines.next().map(str::trim) != Some(PHRASE_HEADER) {
+            return Err(format!("Expected the header {:?}", PHRASE_HEADER));
+        }
+        let mut phrase = Phrase {
+            number,
+            version,
+            ..Default::default()
+        };
+        for i in 0..limits::N_STEPS {
+            let line = lines
+                .next()
+                .ok_or_else(|| format!("Phrase {:02x}: missing row {:x}", number, i))?;
+            let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+            phrase.steps[i] = parse_row(&cells, i, version)
+                .map_err(|e| format!("Phrase {:02x}: {}", number, e))?;
+        }
+        if lines.next().is_some() {
+            return Err(format!("Phrase {:02x}: more than 16 rows", number));
+        }
+        Ok(phrase)
+    }
+}
+
+/// Every cell of a row but its number.
+fn row_cells(row: &PhraseRow) -> String {
+    let mut cells = Vec::from([
+        row.note.map(|n| n.to_string()).unwrap_or_default(),
+        hex_cell(row.velocity),
+        hex_cell(row.instrument),
+    ]);
+    for fx in &row.fx {
+        match fx {
+            Some((command, value)) => {
+                cells.push(command.trim().to_string());
+                cells.push(format!("{:02x}", value));
+            }
+            None => cells.extend([String::new(), String::new()]),
+        }
+    }
+    cells.join(",")
+}
+
+/// `cells` starting with the row number.
+fn parse_row(cells: &[&str], i: usize, version: Version) -> core::result::Result<Step, String> {
+    if cells.len() != 10 {
+        return Err(format!(
+            "row {:x} has {} cells, expected 10",
+            i,
+            cells.len()
+        ));
+    }
+    if usize::from_str_radix(cells[0], 16) != Ok(i) {
+        return Err(format!("expected row {:x}, got {:?}", i, cells[0]));
+    }
+    let mut step = Step::default();
+    if !cells[1].is_empty() {
+        step.set_note(Some(cells[1].parse()?));
+    }
+    step.set_velocity(parse_hex(cells[2], "velocity")?);
+    step.set_instrument(parse_hex(cells[3], "instrument")?);
+    for slot in 0..limits::FX_COLUMNS {
+        let (command, value) = (cells[4 + slot * 2], cells[5 + slot * 2]);
+        if command.is_empty() {
+            if !value.is_empty() {
+                return Err(format!("row {:x} has an FX value without a command", i));
+            }
+            continue;
+        }
+        let value = parse_hex(value, "FX value")?.unwrap_or(0);
+        let fx = match FX::new(version, command, value) {
+            Some(fx) => fx,
+            None => FX {
+                command: parse_hex(command, "FX command")?.unwrap_or(255),
+                value,
+            },
+        };
+        step.set_fx(slot, Some(fx))?;
+    }
+    Ok(step)
+}
+
+impl Song {
+    /// Every non-empty phrase as CSV. This is the format of [`Phrase::to_csv`]
+    /// with a leading `phrase` column holding the phrase number in hex.
+    pub fn export_phrases_csv(&self) -> String {
+        let mut csv = String::from(SONG_HEADER);
+        csv.push('\n');
+        for phrase in self.phrases.iter().filter(|p| !p.is_empty()) {
+            for (i, row) in phrase.rows().iter().enumerate() {
+                csv += &format!("{:02x},{:x},{}\n", phrase.number, i, row_cells(row));
+            }
+        }
+        csv
+    }
+
+    /// Read phrases written by `export_phrases_csv`, replacing the phrases it
+    /// holds. Rows are grouped into phrases by their `phrase` column, and each
+    /// phrase needs all 16 of its rows, in order, as [`Phrase::from_csv`] does.
+    /// Nothing is changed if any of it fails to parse.
+    pub fn import_phrases_csv(&mut self, csv: &str) -> core::result::Result<(), String> {
+        let lines: Vec<&str> = csv.lines().filter(|l| !l.trim().is_empty()).collect();
+        if lines.first().map(|l| l.trim()) != Some(SONG_HEADER) {
+            return Err(format!("Expected the header {:?}", SONG_HEADER));
+        }
+        let mut imported: Vec<Phrase> = Vec::new();
+        let mut rows = lines[1..].iter().peekable();
+        while let Some(first) = rows.next() {
+            let cell = first.split(',').next().unwrap_or_default().trim();
+            let number = parse_hex(cell, "phrase number")?
+                .filter(|&p| (p as usize) < Self::N_PHRASES)
+                .ok_or_else(|| format!("Invalid phrase number {:?}", cell))?;
+            // The phrase's rows without their phrase column, as Phrase::from_csv takes them
+            let mut phrase_csv = String::from(PHRASE_HEADER);
+            let mut count = 0;
+            let mut line = Some(*first);
+            while let Some(l) = line {
+                phrase_csv.push('\n');
+                phrase_csv += l.split_once(',').map(|(_, rest)| rest).unwrap_or_default();
+                count += 1;
+                line = rows
+                    .next_if(|l| l.split(',').next().map(str::trim) == Some(cell))
+                    .copied();
+            }
+            if count != limits::N_STEPS {
+                return Err(format!(
+                    "Phrase {:02x} has {} rows, expected 16",
+                    number, count
+                ));
+            }
+            imported.push(Phrase::from_csv(number, self.version, &phrase_csv)?);
+        }
+        for phrase in imported {
+            let number = phrase.number as usize;
+            self.phrases[number] = phrase;
+        }
+        Ok(())
+    }
+}
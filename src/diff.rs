@@ -0,0 +1,164 @@
+use crate::*;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+/// One part of a song that differs between two songs. Indices are slot numbers.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SongChange {
+    /// Version, directory, name, tempo, transpose, quantize or key.
+    Metadata,
+    SongRow(usize),
+    Phrase(usize),
+    Chain(usize),
+    Instrument(usize),
+    Table(usize),
+    Groove(usize),
+    Scale(usize),
+    MidiMapping(usize),
+    MixerSettings,
+    EffectsSettings,
+    MidiSettings,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SongDiff {
+    pub changes: Vec<SongChange>,
+}
+
+impl SongDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+fn diff_slots<T: PartialEq>(
+    a: &[T],
+    b: &[T],
+    change: fn(usize) -> SongChange,
+    changes: &mut Vec<SongChange>,
+) {
+    for i in 0..a.len().max(b.len()) {
+        if a.get(i) != b.get(i) {
+            changes.push(change(i));
+        }
+    }
+}
+
+impl Song {
+    /// Every part of the song that differs from `other`, in file order.
+    pub fn diff(&self, other: &Song) -> SongDiff {
+        let mut changes = vec![];
+        if self.version != other.version
+            || self.directory != other.directory
+            || self.name != other.name
+            || self.tempo != other.tempo
+            || self.transpose != other.transpose
+            || self.quantize != other.quantize
+            || self.key != other.key
+        {
+            changes.push(SongChange::Metadata);
+        }
+
+        let a = self.song.steps.chunks(8);
+        let b = other.song.steps.chunks(8);
+        for (row, (a, b)) in a.zip(b).enumerate() {
+            if a != b {
+                changes.push(SongChange::SongRow(row));
+            }
+        }
+
+        diff_slots(
+            &self.phrases,
+            &other.phrases,
+            SongChange::Phrase,
+            &mut changes,
+        );
+        diff_slots(&self.chains, &other.chains, SongChange::Chain, &mut changes);
+        diff_slots(
+            &self.instruments,
+            &other.instruments,
+            SongChange::Instrument,
+            &mut changes,
+        );
+        diff_slots(&self.tables, &other.tables, SongChange::Table, &mut changes);
+        diff_slots(
+            &self.grooves,
+            &other.grooves,
+            SongChange::Groove,
+            &mut changes,
+        );
+        diff_slots(&self.scales, &other.scales, SongChange::Scale, &mut changes);
+        diff_slots(
+            &self.midi_mappings,
+            &other.midi_mappings,
+            SongChange::MidiMapping,
+            &mut changes,
+        );
+
+        if self.mixer_settings != other.mixer_settings {
+            changes.push(SongChange::MixerSettings);
+        }
+        if self.effects_settings != other.effects_settings {
+            changes.push(SongChange::EffectsSettings);
+        }
+        if self.midi_settings != other.midi_settings {
+            changes.push(SongChange::MidiSettings);
+        }
+        SongDiff { changes }
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is fixed, so hashes can be
+/// stored and compared across runs, builds and platforms.
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    // Lengths are hashed as usize; widen them so 32 and 64-bit targets agree
+    fn write_usize(&mut self, i: usize) {
+        self.write(&(i as u64).to_le_bytes());
+    }
+}
+
+impl Song {
+    /// A stable fingerprint of the song's parsed contents: the version number,
+    /// metadata, song rows, every phrase, chain, instrument, table, groove, scale
+    /// and MIDI mapping, and the mixer, effects and MIDI settings. Bytes this
+    /// crate doesn't parse (the version string, reserved regions, padding after
+    /// names) are not included, so two files that parse the same hash the same.
+    pub fn content_hash(&self) -> u64 {
+        let mut h = Fnv(0xcbf29ce484222325);
+        self.version.hash(&mut h);
+        self.directory.hash(&mut h);
+        self.transpose.hash(&mut h);
+        self.tempo.to_bits().hash(&mut h);
+        self.quantize.hash(&mut h);
+        self.name.hash(&mut h);
+        self.key.hash(&mut h);
+
+        self.song.hash(&mut h);
+        self.phrases.hash(&mut h);
+        self.chains.hash(&mut h);
+        self.instruments.hash(&mut h);
+        self.tables.hash(&mut h);
+        self.grooves.hash(&mut h);
+        self.scales.hash(&mut h);
+
+        self.mixer_settings.hash(&mut h);
+        self.effects_settings.hash(&mut h);
+        self.midi_settings.hash(&mut h);
+        self.midi_mappings.hash(&mut h);
+        h.finish()
+    }
+}
@@ -0,0 +1,57 @@
+use crate::reader::*;
+use crate::Song;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Read every `.m8s` song in `path`, sorted by file name.
+///
+/// Each song's result is returned alongside its path, so one unreadable or
+/// corrupt file does not prevent the others from loading: it holds an
+/// [`M8Error::Io`] if the file couldn't be read, and an [`M8Error::Parse`] if it
+/// isn't a valid song. Only errors listing the directory itself are returned as
+/// `Err`.
+pub fn read_dir(path: impl AsRef<Path>) -> M8Result<Vec<(PathBuf, M8Result<Song>)>> {
+    let mut songs = vec![];
+    read_songs(path.as_ref(), false, &mut songs)?;
+    Ok(songs)
+}
+
+/// Like [`read_dir`], but also reads the songs in every subdirectory.
+pub fn read_dir_recursive(path: impl AsRef<Path>) -> M8Result<Vec<(PathBuf, M8Result<Song>)>> {
+    let mut songs = vec![];
+    read_songs(path.as_ref(), true, &mut songs)?;
+    Ok(songs)
+}
+
+fn read_songs(
+    dir: &Path,
+    recursive: bool,
+    songs: &mut Vec<(PathBuf, M8Result<Song>)>,
+) -> M8Result<()> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+
+    for path in paths {
+        if path.is_dir() {
+            if recursive {
+                read_songs(&path, recursive, songs)?;
+            }
+        } else if is_song(&path) {
+            let song = fs::read(&path)
+                .map_err(M8Error::from)
+                .and_then(|buf| Ok(Song::from_bytes(buf)?));
+            songs.push((path, song));
+        }
+    }
+    Ok(())
+}
+
+fn is_song(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("m8s"))
+        .unwrap_or(false)
+}
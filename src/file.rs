@@ -0,0 +1,65 @@
+use crate::reader::*;
+use crate::*;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Any file the M8 saves, as detected by [`M8File::from_bytes`].
+#[derive(PartialEq, Debug, Clone)]
+pub enum M8File {
+    Song(Box<Song>),
+    Instrument(Instrument),
+    Scale(Scale),
+    Theme(Theme),
+}
+
+impl M8File {
+    const MAGIC: &'static [u8] = b"M8VERSION\0";
+
+    #[cfg(feature = "std")]
+    pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes(buf)?)
+    }
+
+    /// Parse a file of any kind. All M8 files start with the same version
+    /// header, so the kind is told apart by the file's length: each kind is
+    /// at least as long as its data, and shorter than the next largest kind.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
+        if !buf.starts_with(Self::MAGIC) {
+            return Err(ParseError(
+                "Not an M8 file: missing the M8VERSION header".to_string(),
+            ));
+        }
+        let version = Version::from_reader(&Reader::new(buf[..Version::SIZE].to_vec()))?;
+        let len = buf.len();
+        let (kind, file) = if len >= Song::byte_size(version) {
+            (
+                "song",
+                Song::from_bytes(buf).map(|s| Self::Song(Box::new(s))),
+            )
+        } else if len >= Version::SIZE + Instrument::SIZE {
+            (
+                "instrument",
+                Instrument::from_bytes(buf).map(Self::Instrument),
+            )
+        } else if len >= Version::SIZE + Scale::SIZE {
+            ("scale", Scale::from_bytes(buf).map(Self::Scale))
+        } else if len >= Version::SIZE + Theme::SIZE {
+            ("theme", Theme::from_bytes(buf).map(Self::Theme))
+        } else {
+            return Err(ParseError(format!(
+                "File is {} bytes, too short to be any M8 file",
+                len
+            )));
+        };
+        file.map_err(|e| {
+            ParseError(format!(
+                "Could not read {} byte file as a {}: {}",
+                len, kind, e.0
+            ))
+        })
+    }
+}
@@ -0,0 +1,26 @@
+//! The example songs this crate is tested against, embedded so that crates
+//! building on it can use them in their own tests without shipping the files.
+//! Only available with the `test-fixtures` feature.
+
+use crate::*;
+
+/// `DEFAULT.m8s`: the empty song firmware 2.7 starts with, named `DEFAULT`.
+pub fn empty_song_bytes() -> &'static [u8] {
+    include_bytes!("../examples/songs/DEFAULT.m8s")
+}
+
+/// `TEST-FILE.m8s`: a firmware 3.0.4 song with one instrument of each kind
+/// (slots 1-7) and a chain playing on each of tracks 1-7.
+pub fn test_song_bytes() -> &'static [u8] {
+    include_bytes!("../examples/songs/TEST-FILE.m8s")
+}
+
+/// [`empty_song_bytes`], parsed.
+pub fn empty_song() -> Song {
+    Song::from_bytes(empty_song_bytes().to_vec()).expect("DEFAULT.m8s should parse")
+}
+
+/// [`test_song_bytes`], parsed.
+pub fn test_song() -> Song {
+    Song::from_bytes(test_song_bytes().to_vec()).expect("TEST-FILE.m8s should parse")
+}
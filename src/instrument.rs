@@ -1,9 +1,15 @@
+use crate::fx::*;
 use crate::reader::*;
 use crate::version::*;
+use crate::writer::*;
 
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use arr_macro::arr;
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default, Hash)]
 pub enum Instrument {
     WavSynth(WavSynth),
     MacroSynth(MacroSynth),
@@ -12,19 +18,126 @@ pub enum Instrument {
     FMSynth(FMSynth),
     HyperSynth(HyperSynth),
     External(ExternalInst),
+    #[default]
     None,
 }
-impl Default for Instrument {
-    fn default() -> Self {
-        Self::None
+
+#[derive(PartialEq, Debug, Clone, Copy, Hash)]
+pub enum InstrumentKind {
+    WavSynth,
+    MacroSynth,
+    Sampler,
+    MIDIOut,
+    FMSynth,
+    HyperSynth,
+    External,
+}
+
+/// From 3.0 a modulator can also target the other modulators.
+const MOD_DESTINATIONS: [&str; 4] = ["MOD AMT", "MOD RATE", "MOD BOTH", "MOD BINV"];
+
+/// The filter, amp and mixer commands, which every kind but MIDI out has after
+/// its own.
+const SYNTH_COMMANDS: [&str; 10] = [
+    "FIL", "CUT", "RES", "AMP", "LIM", "PAN", "DRY", "SCH", "SDL", "SRV",
+];
+
+impl InstrumentKind {
+    /// The commands from `INSTRUMENT_COMMAND_START`, before the modulators'.
+    fn own_commands(self) -> Vec<&'static str> {
+        let own: &[&str] = match self {
+            Self::WavSynth => &["VOL", "PIT", "FIN", "OSC", "SIZ", "MUL", "WRP", "MIR"],
+            Self::MacroSynth => &["VOL", "PIT", "FIN", "OSC", "TBR", "COL", "DEG", "RED"],
+            Self::Sampler => &["VOL", "PIT", "FIN", "PLY", "STA", "LOP", "LEN", "DEG"],
+            Self::FMSynth => &["VOL", "PIT", "FIN", "ALG", "FM1", "FM2", "FM3", "FM4"],
+            Self::HyperSynth => &["VOL", "PIT", "FIN", "CRD", "SHF", "SWM", "WID", "SUB"],
+            Self::External => &["VOL", "PIT", "MPB", "MPG", "CCA", "CCB", "CCC", "CCD"],
+            Self::MIDIOut => {
+                return vec![
+                    "VOL", "PIT", "MPB", "MPG", "CCA", "CCB", "CCC", "CCD", "CCE", "CCF", "CCG",
+                    "CCH", "CCI", "CCJ",
+                ]
+            }
+        };
+        let mut commands = own.to_vec();
+        commands.extend(SYNTH_COMMANDS);
+        commands
+    }
+
+    /// What this kind's modulators can target, in the order of the MOD page's
+    /// DEST list, before the modulator destinations.
+    fn own_destinations(self) -> &'static [&'static str] {
+        match self {
+            Self::WavSynth => &[
+                "OFF", "VOLUME", "PITCH", "SIZE", "MULT", "WARP", "MIRROR", "CUTOFF", "RES", "AMP",
+                "PAN",
+            ],
+            Self::MacroSynth => &[
+                "OFF", "VOLUME", "PITCH", "TIMBRE", "COLOR", "DEGRADE", "REDUX", "CUTOFF", "RES",
+                "AMP", "PAN",
+            ],
+            Self::Sampler => &[
+                "OFF", "VOLUME", "PITCH", "LOOP ST", "LENGTH", "DEGRADE", "CUTOFF", "RES", "AMP",
+                "PAN",
+            ],
+            Self::MIDIOut => &[
+                "OFF", "CCA", "CCB", "CCC", "CCD", "CCE", "CCF", "CCG", "CCH", "CCI", "CCJ",
+            ],
+            Self::FMSynth => &[
+                "OFF", "VOLUME", "PITCH", "MOD1", "MOD2", "MOD3", "MOD4", "CUTOFF", "RES", "AMP",
+                "PAN",
+            ],
+            Self::HyperSynth => &[
+                "OFF", "VOLUME", "PITCH", "SHIFT", "SWARM", "WIDTH", "SUBOSC", "CUTOFF", "RES",
+                "AMP", "PAN",
+            ],
+            Self::External => &[
+                "OFF", "VOLUME", "CUTOFF", "RES", "AMP", "PAN", "CCA", "CCB", "CCC", "CCD",
+            ],
+        }
+    }
+
+    /// The names of the destinations a modulator of this kind can have on
+    /// firmware `version`, indexed by its `dest`.
+    pub fn destination_names(self, version: Version) -> Vec<&'static str> {
+        let mut names = self.own_destinations().to_vec();
+        if version.at_least(3, 0) {
+            names.extend(MOD_DESTINATIONS);
+        }
+        names
     }
 }
+
 impl Instrument {
-    const SIZE: usize = 215;
+    pub(crate) const SIZE: usize = 215;
+    const EMPTY_KIND: u8 = 0xFF;
 
-    pub fn read(reader: &mut impl std::io::Read) -> Result<Self> {
-        let mut buf: Vec<u8> = vec![];
-        reader.read_to_end(&mut buf).unwrap();
+    #[cfg(feature = "std")]
+    pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes(buf)?)
+    }
+
+    /// Parse the full contents of a file that's already in memory.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
+        Self::parse(buf, None)
+    }
+
+    /// Like `read`, but ignores the version in the file's header and uses the
+    /// layout of `version` instead.
+    #[cfg(feature = "std")]
+    pub fn read_with_version(reader: &mut impl std::io::Read, version: Version) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes_with_version(buf, version)?)
+    }
+
+    /// Like `from_bytes`, but ignores the version in the file's header and uses
+    /// the layout of `version` instead. The header bytes are still skipped.
+    pub fn from_bytes_with_version(buf: Vec<u8>, version: Version) -> Result<Self> {
+        Self::parse(buf, Some(version))
+    }
+
+    fn parse(buf: Vec<u8>, version: Option<Version>) -> Result<Self> {
         let len = buf.len();
         let reader = Reader::new(buf);
 
@@ -33,7 +146,13 @@ impl Instrument {
                 "File is not long enough to be a M8 Instrument".to_string(),
             ));
         }
-        let version = Version::from_reader(&reader)?;
+        let version = match version {
+            Some(v) => {
+                reader.try_set_pos(Version::SIZE)?;
+                v
+            }
+            None => Version::from_reader(&reader)?,
+        };
         if version.at_least(3, 0) {
             Self::from_reader3(&reader, 0, version)
         } else {
@@ -41,6 +160,238 @@ impl Instrument {
         }
     }
 
+    /// Parse one instrument slot cut out of a song, without a version header.
+    /// `data` must hold at least the slot's 215 bytes.
+    pub fn from_slot_bytes(data: &[u8], number: u8, version: Version) -> Result<Self> {
+        if data.len() < Self::SIZE {
+            return Err(ParseError(format!(
+                "Instrument slot is {} bytes, expected {}",
+                data.len(),
+                Self::SIZE
+            )));
+        }
+        let reader = Reader::new(data[..Self::SIZE].to_vec());
+        if version.at_least(3, 0) {
+            Self::from_reader3(&reader, number, version)
+        } else {
+            Self::from_reader2(&reader, number, version)
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::None;
+    }
+
+    /// Write an empty slot the way the device does.
+    pub(crate) fn write_empty(w: &mut Writer) {
+        let start = w.pos();
+        w.fill(0xFF, 13);
+        w.write_bytes(&[1, 1]);
+        w.fill_till(0xFF, start + crate::layout::instrument::SAMPLE_PATH);
+        w.fill_till(0, start + Self::SIZE);
+    }
+
+    /// Check that firmware `version` can store this instrument, before writing
+    /// anything. HyperSynths and externals need 3.0+. Before 3.0 the synths'
+    /// modulators are fixed: two AHD envelopes, then two LFOs.
+    pub fn check_writable(&self, version: Version) -> core::result::Result<(), String> {
+        let synth_params = match self {
+            Self::HyperSynth(_) | Self::External(_) if !version.at_least(3, 0) => {
+                return Err(format!(
+                    "{:?} instruments need firmware 3.0, not {}",
+                    self.kind().unwrap(),
+                    version
+                ))
+            }
+            _ if version.at_least(3, 0) => return Ok(()),
+            Self::WavSynth(s) => &s.synth_params,
+            Self::MacroSynth(s) => &s.synth_params,
+            Self::Sampler(s) => &s.synth_params,
+            Self::FMSynth(s) => &s.synth_params,
+            _ => return Ok(()),
+        };
+        if !matches!(
+            synth_params.mods,
+            [Mod::AHDEnv(_), Mod::AHDEnv(_), Mod::LFO(_), Mod::LFO(_)]
+        ) {
+            return Err(format!(
+                "Instrument {:02x}: firmware {} only stores AHD, AHD, LFO, LFO modulators",
+                self.number().unwrap(),
+                version
+            ));
+        }
+        Ok(())
+    }
+
+    /// Write the instrument over the 215 byte slot at `w`'s position, for
+    /// firmware `version`, in the order it's read. See
+    /// [`check_writable`](Self::check_writable) for what can fail.
+    ///
+    /// Bytes this crate doesn't parse are left as they are when the slot already
+    /// holds an instrument of the same kind, so rewriting a parsed song is
+    /// lossless. Otherwise the slot is zeroed first, so that an instrument written
+    /// over another kind doesn't pick up its settings.
+    pub fn write(&self, w: &mut Writer, version: Version) -> core::result::Result<(), String> {
+        self.check_writable(version)?;
+        let start = w.pos();
+        let kind = match self {
+            Self::WavSynth(_) => WavSynth::KIND,
+            Self::MacroSynth(_) => MacroSynth::KIND,
+            Self::Sampler(_) => Sampler::KIND,
+            Self::MIDIOut(_) => MIDIOut::KIND,
+            Self::FMSynth(_) => FMSynth::KIND,
+            Self::HyperSynth(_) => HyperSynth::KIND,
+            Self::External(_) => ExternalInst::KIND,
+            Self::None => Self::EMPTY_KIND,
+        };
+        if w.as_bytes().get(start) != Some(&kind) {
+            if *self == Self::None {
+                Self::write_empty(w);
+                return Ok(());
+            }
+            w.fill(0, Self::SIZE);
+            w.seek(start);
+        }
+        match self {
+            Self::WavSynth(s) => s.write(w, version),
+            Self::MacroSynth(s) => s.write(w, version),
+            Self::Sampler(s) => s.write(w, version),
+            Self::MIDIOut(s) => s.write(w, version),
+            Self::FMSynth(s) => s.write(w, version),
+            Self::HyperSynth(s) => s.write(w),
+            Self::External(s) => s.write(w),
+            Self::None => (),
+        }
+        w.seek(start + Self::SIZE);
+        Ok(())
+    }
+
+    /// The instrument commands available to this instrument, with their command
+    /// bytes, as its kind names them (e.g. `ALG` for an FM synth's `0x83`). Empty
+    /// slots have none.
+    ///
+    /// The kind's own commands start at `INSTRUMENT_COMMAND_START`. Then each
+    /// modulator has [`Mod::COMMANDS`] bytes, named for its type and slot (`AT1`
+    /// is the first modulator's attack). Types with fewer parameters leave the
+    /// rest of their bytes unused. Bytes past the last instrument command of
+    /// `version` aren't listed.
+    pub fn command_table(&self, version: Version) -> Vec<(u8, &'static str)> {
+        let (kind, mods) = match (self.kind(), self.mods()) {
+            (Some(kind), Some(mods)) => (kind, mods),
+            _ => return vec![],
+        };
+        let own = kind.own_commands();
+        let mod_commands = mods.iter().enumerate().flat_map(|(slot, m)| {
+            let start = own.len() + slot * Mod::COMMANDS;
+            m.command_names(slot)
+                .iter()
+                .enumerate()
+                .map(move |(i, c)| (start + i, *c))
+        });
+        own.iter()
+            .copied()
+            .enumerate()
+            .chain(mod_commands)
+            .filter(|(i, _)| *i < instrument_commands(version).len())
+            .map(|(i, c)| (INSTRUMENT_COMMAND_START + i as u8, c))
+            .collect()
+    }
+
+    pub fn kind(&self) -> Option<InstrumentKind> {
+        match self {
+            Self::WavSynth(_) => Some(InstrumentKind::WavSynth),
+            Self::MacroSynth(_) => Some(InstrumentKind::MacroSynth),
+            Self::Sampler(_) => Some(InstrumentKind::Sampler),
+            Self::MIDIOut(_) => Some(InstrumentKind::MIDIOut),
+            Self::FMSynth(_) => Some(InstrumentKind::FMSynth),
+            Self::HyperSynth(_) => Some(InstrumentKind::HyperSynth),
+            Self::External(_) => Some(InstrumentKind::External),
+            Self::None => None,
+        }
+    }
+
+    pub fn number(&self) -> Option<u8> {
+        match self {
+            Self::WavSynth(s) => Some(s.number),
+            Self::MacroSynth(s) => Some(s.number),
+            Self::Sampler(s) => Some(s.number),
+            Self::MIDIOut(s) => Some(s.number),
+            Self::FMSynth(s) => Some(s.number),
+            Self::HyperSynth(s) => Some(s.number),
+            Self::External(s) => Some(s.number),
+            Self::None => None,
+        }
+    }
+
+    pub fn set_number(&mut self, number: u8) {
+        match self {
+            Self::WavSynth(s) => s.number = number,
+            Self::MacroSynth(s) => s.number = number,
+            Self::Sampler(s) => s.number = number,
+            Self::MIDIOut(s) => s.number = number,
+            Self::FMSynth(s) => s.number = number,
+            Self::HyperSynth(s) => s.number = number,
+            Self::External(s) => s.number = number,
+            Self::None => (),
+        }
+    }
+
+    pub fn mods(&self) -> Option<&[Mod; 4]> {
+        match self {
+            Self::WavSynth(s) => Some(&s.synth_params.mods),
+            Self::MacroSynth(s) => Some(&s.synth_params.mods),
+            Self::Sampler(s) => Some(&s.synth_params.mods),
+            Self::MIDIOut(s) => Some(&s.mods),
+            Self::FMSynth(s) => Some(&s.synth_params.mods),
+            Self::HyperSynth(s) => Some(&s.synth_params.mods),
+            Self::External(s) => Some(&s.synth_params.mods),
+            Self::None => None,
+        }
+    }
+
+    fn mods_mut(&mut self) -> Option<&mut [Mod; 4]> {
+        match self {
+            Self::WavSynth(s) => Some(&mut s.synth_params.mods),
+            Self::MacroSynth(s) => Some(&mut s.synth_params.mods),
+            Self::Sampler(s) => Some(&mut s.synth_params.mods),
+            Self::MIDIOut(s) => Some(&mut s.mods),
+            Self::FMSynth(s) => Some(&mut s.synth_params.mods),
+            Self::HyperSynth(s) => Some(&mut s.synth_params.mods),
+            Self::External(s) => Some(&mut s.synth_params.mods),
+            Self::None => None,
+        }
+    }
+
+    /// Equality ignoring the instrument's number and its four modulators. Every
+    /// other field is compared: the kind, name, transpose and table tick, the
+    /// kind's own parameters (e.g. the sample path, or the FM operators), and
+    /// for the synths the shared volume, pitch, filter, amp, mixer and send
+    /// settings. Two empty slots are equal.
+    pub fn core_eq(&self, other: &Self) -> bool {
+        let strip = |i: &Self| {
+            let mut i = i.clone();
+            i.set_number(0);
+            for m in i.mods_mut().into_iter().flatten() {
+                *m = AHDEnv::default().into_mod();
+            }
+            i
+        };
+        strip(self) == strip(other)
+    }
+
+    /// One line per modulator, in the order of the instrument's MOD page, with
+    /// destinations named as on firmware `version` (see
+    /// [`InstrumentKind::destination_names`]). Empty slots have none.
+    pub fn describe_mods(&self, version: Version) -> Vec<String> {
+        match (self.kind(), self.mods()) {
+            (Some(kind), Some(mods)) => {
+                let names = kind.destination_names(version);
+                mods.iter().map(|m| m.describe(&names)).collect()
+            }
+            _ => vec![],
+        }
+    }
+
     pub(crate) fn from_reader2(reader: &Reader, number: u8, version: Version) -> Result<Self> {
         let start_pos = reader.pos();
         let kind = reader.read();
@@ -53,8 +404,19 @@ impl Instrument {
             (0, 0, 0)
         };
 
-        let finalize = || -> () {
-            reader.set_pos(start_pos + Self::SIZE);
+        // Each kind reads only the fields it knows about, so realign to the start of
+        // the next instrument. Reading past it means the layout is wrong.
+        let finalize = || -> Result<()> {
+            if reader.pos() > start_pos + Self::SIZE {
+                return Err(ParseError(format!(
+                    "Instrument {} read {} bytes, more than its {} byte slot",
+                    number,
+                    reader.pos() - start_pos,
+                    Self::SIZE
+                )));
+            }
+            reader.try_set_pos(start_pos + Self::SIZE)?;
+            Ok(())
         };
 
         Ok(match kind {
@@ -66,7 +428,7 @@ impl Instrument {
                 let warp = reader.read();
                 let mirror = reader.read();
                 let synth_params = SynthParams::from_reader2(reader, volume, pitch, fine_tune)?;
-                finalize();
+                finalize()?;
                 Self::WavSynth(WavSynth {
                     number,
                     name,
@@ -89,7 +451,7 @@ impl Instrument {
                 let degrade = reader.read();
                 let redux = reader.read();
                 let synth_params = SynthParams::from_reader2(reader, volume, pitch, fine_tune)?;
-                finalize();
+                finalize()?;
                 Self::MacroSynth(MacroSynth {
                     number,
                     name,
@@ -113,9 +475,9 @@ impl Instrument {
                 let length = reader.read();
                 let degrade = reader.read();
                 let synth_params = SynthParams::from_reader2(reader, volume, pitch, fine_tune)?;
-                reader.set_pos(start_pos + 0x57);
+                reader.try_set_pos(start_pos + crate::layout::instrument::SAMPLE_PATH)?;
                 let sample_path = reader.read_string(128);
-                finalize();
+                finalize()?;
                 Self::Sampler(Sampler {
                     number,
                     name,
@@ -140,8 +502,8 @@ impl Instrument {
                 let program_change = reader.read();
                 reader.read_bytes(3); // discard
                 let custom_cc: [ControlChange; 8] = arr![ControlChange::from_reader(reader)?; 8];
-                let mods = arr![AHDEnv::default().to_mod(); 4];
-                finalize();
+                let mods = arr![AHDEnv::default().into_mod(); 4];
+                finalize()?;
                 Self::MIDIOut(MIDIOut {
                     number,
                     name,
@@ -161,30 +523,30 @@ impl Instrument {
                 let algo = reader.read();
                 let mut operators: [Operator; 4] = arr![Operator::default(); 4];
                 if version.at_least(1, 4) {
-                    for i in 0..4 {
-                        operators[i].shape = reader.read();
+                    for op in operators.iter_mut() {
+                        op.shape = reader.read();
                     }
                 }
-                for i in 0..4 {
-                    operators[i].ratio = reader.read();
-                    operators[i].ratio_fine = reader.read();
+                for op in operators.iter_mut() {
+                    op.ratio = reader.read();
+                    op.ratio_fine = reader.read();
                 }
-                for i in 0..4 {
-                    operators[i].level = reader.read();
-                    operators[i].feedback = reader.read();
+                for op in operators.iter_mut() {
+                    op.level = reader.read();
+                    op.feedback = reader.read();
                 }
-                for i in 0..4 {
-                    operators[i].mod_a = reader.read();
+                for op in operators.iter_mut() {
+                    op.mod_a = reader.read();
                 }
-                for i in 0..4 {
-                    operators[i].mod_b = reader.read();
+                for op in operators.iter_mut() {
+                    op.mod_b = reader.read();
                 }
                 let mod1 = reader.read();
                 let mod2 = reader.read();
                 let mod3 = reader.read();
                 let mod4 = reader.read();
                 let synth_params = SynthParams::from_reader2(reader, volume, pitch, fine_tune)?;
-                finalize();
+                finalize()?;
 
                 Self::FMSynth(FMSynth {
                     number,
@@ -202,10 +564,15 @@ impl Instrument {
                 })
             }
             0xFF => {
-                finalize();
+                finalize()?;
                 Self::None
             }
-            _ => panic!("Instrument type {} not supported", kind),
+            _ => {
+                return Err(ParseError(format!(
+                    "Instrument type {} not supported",
+                    kind
+                )))
+            }
         })
     }
 
@@ -222,8 +589,19 @@ impl Instrument {
             (0, 0, 0)
         };
 
-        let finalize = || -> () {
-            reader.set_pos(start_pos + Self::SIZE);
+        // Each kind reads only the fields it knows about, so realign to the start of
+        // the next instrument. Reading past it means the layout is wrong.
+        let finalize = || -> Result<()> {
+            if reader.pos() > start_pos + Self::SIZE {
+                return Err(ParseError(format!(
+                    "Instrument {} read {} bytes, more than its {} byte slot",
+                    number,
+                    reader.pos() - start_pos,
+                    Self::SIZE
+                )));
+            }
+            reader.try_set_pos(start_pos + Self::SIZE)?;
+            Ok(())
         };
 
         Ok(match kind {
@@ -234,7 +612,7 @@ impl Instrument {
                 let warp = reader.read();
                 let mirror = reader.read();
                 let synth_params = SynthParams::from_reader3(reader, volume, pitch, fine_tune, 30)?;
-                finalize();
+                finalize()?;
                 Self::WavSynth(WavSynth {
                     number,
                     name,
@@ -256,7 +634,7 @@ impl Instrument {
                 let degrade = reader.read();
                 let redux = reader.read();
                 let synth_params = SynthParams::from_reader3(reader, volume, pitch, fine_tune, 30)?;
-                finalize();
+                finalize()?;
                 Self::MacroSynth(MacroSynth {
                     number,
                     name,
@@ -279,9 +657,9 @@ impl Instrument {
                 let length = reader.read();
                 let degrade = reader.read();
                 let synth_params = SynthParams::from_reader3(reader, volume, pitch, fine_tune, 29)?;
-                reader.set_pos(start_pos + 0x57);
+                reader.try_set_pos(start_pos + crate::layout::instrument::SAMPLE_PATH)?;
                 let sample_path = reader.read_string(128);
-                finalize();
+                finalize()?;
                 Self::Sampler(Sampler {
                     number,
                     name,
@@ -307,7 +685,7 @@ impl Instrument {
                 let custom_cc: [ControlChange; 8] = arr![ControlChange::from_reader(reader)?; 8];
                 let _discard = reader.read_bytes(25);
                 let mods = arr![Mod::from_reader(reader)?; 4];
-                finalize();
+                finalize()?;
                 Self::MIDIOut(MIDIOut {
                     number,
                     name,
@@ -326,30 +704,30 @@ impl Instrument {
                 let algo = reader.read();
                 let mut operators: [Operator; 4] = arr![Operator::default(); 4];
                 if version.at_least(1, 4) {
-                    for i in 0..4 {
-                        operators[i].shape = reader.read();
+                    for op in operators.iter_mut() {
+                        op.shape = reader.read();
                     }
                 }
-                for i in 0..4 {
-                    operators[i].ratio = reader.read();
-                    operators[i].ratio_fine = reader.read();
+                for op in operators.iter_mut() {
+                    op.ratio = reader.read();
+                    op.ratio_fine = reader.read();
                 }
-                for i in 0..4 {
-                    operators[i].level = reader.read();
-                    operators[i].feedback = reader.read();
+                for op in operators.iter_mut() {
+                    op.level = reader.read();
+                    op.feedback = reader.read();
                 }
-                for i in 0..4 {
-                    operators[i].mod_a = reader.read();
+                for op in operators.iter_mut() {
+                    op.mod_a = reader.read();
                 }
-                for i in 0..4 {
-                    operators[i].mod_b = reader.read();
+                for op in operators.iter_mut() {
+                    op.mod_b = reader.read();
                 }
                 let mod1 = reader.read();
                 let mod2 = reader.read();
                 let mod3 = reader.read();
                 let mod4 = reader.read();
                 let synth_params = SynthParams::from_reader3(reader, volume, pitch, fine_tune, 2)?;
-                finalize();
+                finalize()?;
 
                 Self::FMSynth(FMSynth {
                     number,
@@ -377,7 +755,7 @@ impl Instrument {
                 let subosc = reader.read();
                 let synth_params = SynthParams::from_reader3(reader, volume, pitch, fine_tune, 23)?;
 
-                finalize();
+                finalize()?;
                 Self::HyperSynth(HyperSynth {
                     number,
                     name,
@@ -405,7 +783,7 @@ impl Instrument {
                 let ccc = ControlChange::from_reader(reader)?;
                 let ccd = ControlChange::from_reader(reader)?;
                 let synth_params = SynthParams::from_reader3(reader, volume, pitch, fine_tune, 22)?;
-                finalize();
+                finalize()?;
                 Self::External(ExternalInst {
                     number,
                     name,
@@ -425,15 +803,29 @@ impl Instrument {
                 })
             }
             0xFF => {
-                finalize();
+                finalize()?;
                 Self::None
             }
-            _ => panic!("Instrument type {} not supported", kind),
+            _ => {
+                return Err(ParseError(format!(
+                    "Instrument type {} not supported",
+                    kind
+                )))
+            }
         })
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+/// The fields every kind starts with, in the order they're read. Everything but
+/// MIDI out follows them with the volume, pitch and fine tune.
+fn write_header(w: &mut Writer, kind: u8, name: &str, transpose: bool, table_tick: u8) {
+    w.write(kind);
+    w.write_string(name, 12);
+    w.write_bool(transpose);
+    w.write(table_tick);
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct WavSynth {
     pub number: u8,
     pub name: String,
@@ -448,7 +840,18 @@ pub struct WavSynth {
     pub mirror: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl WavSynth {
+    const KIND: u8 = 0x00;
+
+    fn write(&self, w: &mut Writer, version: Version) {
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        self.synth_params.write_tuning(w);
+        w.write_bytes(&[self.shape, self.size, self.mult, self.warp, self.mirror]);
+        self.synth_params.write(w, version, 30);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct MacroSynth {
     pub number: u8,
     pub name: String,
@@ -463,7 +866,24 @@ pub struct MacroSynth {
     pub redux: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl MacroSynth {
+    const KIND: u8 = 0x01;
+
+    fn write(&self, w: &mut Writer, version: Version) {
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        self.synth_params.write_tuning(w);
+        w.write_bytes(&[
+            self.shape,
+            self.timbre,
+            self.color,
+            self.degrade,
+            self.redux,
+        ]);
+        self.synth_params.write(w, version, 30);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct Sampler {
     pub number: u8,
     pub name: String,
@@ -480,7 +900,47 @@ pub struct Sampler {
     pub degrade: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl Sampler {
+    const KIND: u8 = 0x02;
+
+    fn write(&self, w: &mut Writer, version: Version) {
+        let start = w.pos();
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        self.synth_params.write_tuning(w);
+        w.write_bytes(&[
+            self.play_mode,
+            self.slice,
+            self.start,
+            self.loop_start,
+            self.length,
+            self.degrade,
+        ]);
+        self.synth_params.write(w, version, 29);
+        w.seek(start + crate::layout::instrument::SAMPLE_PATH);
+        w.write_string(
+            &self.sample_path,
+            crate::layout::instrument::SAMPLE_PATH_SIZE,
+        );
+    }
+
+    /// Longest path that fits in the file, leaving room for the NUL terminator.
+    pub const MAX_SAMPLE_PATH_LEN: usize = crate::layout::instrument::SAMPLE_PATH_SIZE - 1;
+
+    pub fn set_sample_path(&mut self, path: &str) -> core::result::Result<(), String> {
+        if path.len() > Self::MAX_SAMPLE_PATH_LEN {
+            return Err(format!(
+                "Sample path is {} bytes, the limit is {}: {}",
+                path.len(),
+                Self::MAX_SAMPLE_PATH_LEN,
+                path
+            ));
+        }
+        self.sample_path = path.to_string();
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct FMSynth {
     pub number: u8,
     pub name: String,
@@ -496,7 +956,68 @@ pub struct FMSynth {
     pub mod4: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl FMSynth {
+    const KIND: u8 = 0x04;
+
+    fn write(&self, w: &mut Writer, version: Version) {
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        self.synth_params.write_tuning(w);
+        w.write(self.algo);
+        if version.at_least(1, 4) {
+            for op in &self.operators {
+                w.write(op.shape);
+            }
+        }
+        for op in &self.operators {
+            w.write_bytes(&[op.ratio, op.ratio_fine]);
+        }
+        for op in &self.operators {
+            w.write_bytes(&[op.level, op.feedback]);
+        }
+        for op in &self.operators {
+            w.write(op.mod_a);
+        }
+        for op in &self.operators {
+            w.write(op.mod_b);
+        }
+        w.write_bytes(&[self.mod1, self.mod2, self.mod3, self.mod4]);
+        self.synth_params.write(w, version, 2);
+    }
+
+    /// Algorithms are numbered from 0, one per routing the device offers.
+    pub const N_ALGOS: u8 = 0x0C;
+
+    pub fn set_algo(&mut self, algo: u8) -> core::result::Result<(), String> {
+        if algo >= Self::N_ALGOS {
+            return Err(format!(
+                "FM algorithm {:02X} is out of range, the last is {:02X}",
+                algo,
+                Self::N_ALGOS - 1
+            ));
+        }
+        self.algo = algo;
+        Ok(())
+    }
+
+    pub fn operator(&self, i: usize) -> Option<&Operator> {
+        self.operators.get(i)
+    }
+
+    pub fn set_operator(
+        &mut self,
+        i: usize,
+        operator: Operator,
+    ) -> core::result::Result<(), String> {
+        let op = self
+            .operators
+            .get_mut(i)
+            .ok_or_else(|| format!("Operator {} is out of range", i + 1))?;
+        *op = operator;
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct MIDIOut {
     pub number: u8,
     pub name: String,
@@ -512,7 +1033,37 @@ pub struct MIDIOut {
     pub mods: [Mod; 4],
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl MIDIOut {
+    const KIND: u8 = 0x03;
+
+    /// Write the instrument over the 215 byte slot at `w`'s position, for
+    /// firmware `version`, in the order it's read. The 3 bytes after the program
+    /// change and, in 3.0+, the 25 before the modulators aren't parsed, so
+    /// they're left as they are. Before 3.0 no modulators are stored.
+    ///
+    /// Unlike [`Instrument::write`], this writes over whatever the slot holds.
+    pub fn write(&self, w: &mut Writer, version: Version) {
+        let start = w.pos();
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        w.write(self.port);
+        w.write(self.channel);
+        w.write(self.bank_select);
+        w.write(self.program_change);
+        w.skip(3);
+        for cc in &self.custom_cc {
+            cc.write(w);
+        }
+        if version.at_least(3, 0) {
+            w.seek(start + crate::layout::instrument::MODS);
+            for m in &self.mods {
+                m.write(w);
+            }
+        }
+        w.seek(start + Instrument::SIZE);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct HyperSynth {
     pub number: u8,
     pub name: String,
@@ -528,7 +1079,20 @@ pub struct HyperSynth {
     pub subosc: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl HyperSynth {
+    const KIND: u8 = 0x05;
+
+    /// 3.0+ only.
+    fn write(&self, w: &mut Writer) {
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        self.synth_params.write_tuning(w);
+        w.write_bytes(&self.chord);
+        w.write_bytes(&[self.scale, self.shift, self.swarm, self.width, self.subosc]);
+        self.synth_params.write3(w, 23);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct ExternalInst {
     pub number: u8,
     pub name: String,
@@ -547,7 +1111,22 @@ pub struct ExternalInst {
     pub ccd: ControlChange,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl ExternalInst {
+    const KIND: u8 = 0x06;
+
+    /// 3.0+ only.
+    fn write(&self, w: &mut Writer) {
+        write_header(w, Self::KIND, &self.name, self.transpose, self.table_tick);
+        self.synth_params.write_tuning(w);
+        w.write_bytes(&[self.input, self.port, self.channel, self.bank, self.program]);
+        for cc in [&self.cca, &self.ccb, &self.ccc, &self.ccd] {
+            cc.write(w);
+        }
+        self.synth_params.write3(w, 22);
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct SynthParams {
     pub volume: u8,
     pub pitch: u8,
@@ -589,14 +1168,60 @@ impl SynthParams {
             mixer_reverb: reader.read(),
 
             mods: [
-                AHDEnv::from_reader2(reader)?.to_mod(),
-                AHDEnv::from_reader2(reader)?.to_mod(),
-                LFO::from_reader2(reader)?.to_mod(),
-                LFO::from_reader2(reader)?.to_mod(),
+                AHDEnv::from_reader2(reader)?.into_mod(),
+                AHDEnv::from_reader2(reader)?.into_mod(),
+                LFO::from_reader2(reader)?.into_mod(),
+                LFO::from_reader2(reader)?.into_mod(),
             ],
         })
     }
 
+    /// The volume, pitch and fine tune, which are read with the header.
+    fn write_tuning(&self, w: &mut Writer) {
+        w.write_bytes(&[self.volume, self.pitch, self.fine_tune]);
+    }
+
+    /// The filter, amp and mixer settings, then the modulators. `mod_offset` is
+    /// the one the kind is read with in 3.0+.
+    fn write(&self, w: &mut Writer, version: Version, mod_offset: usize) {
+        if version.at_least(3, 0) {
+            self.write3(w, mod_offset);
+        } else {
+            self.write_mixer(w);
+            for m in &self.mods {
+                match m {
+                    Mod::AHDEnv(m) => m.write2(w),
+                    Mod::LFO(m) => m.write2(w),
+                    // Ruled out by `Instrument::check_writable`
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    fn write3(&self, w: &mut Writer, mod_offset: usize) {
+        self.write_mixer(w);
+        w.skip(mod_offset);
+        for m in &self.mods {
+            m.write(w);
+        }
+    }
+
+    fn write_mixer(&self, w: &mut Writer) {
+        w.write_bytes(&[
+            self.filter_type,
+            self.filter_cutoff,
+            self.filter_res,
+            self.amp,
+            self.limit,
+            self.mixer_pan,
+            self.mixer_dry,
+            self.mixer_chorus,
+            self.mixer_delay,
+            self.mixer_reverb,
+        ]);
+    }
+
     fn from_reader3(
         reader: &Reader,
         volume: u8,
@@ -642,7 +1267,7 @@ impl SynthParams {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub enum Mod {
     AHDEnv(AHDEnv),
     ADSREnv(ADSREnv),
@@ -654,6 +1279,115 @@ pub enum Mod {
 
 impl Mod {
     const SIZE: usize = 6;
+    /// How many instrument command bytes each modulator has.
+    pub const COMMANDS: usize = 5;
+
+    /// The commands for this modulator's parameters when it's in `slot` (0-3).
+    fn command_names(&self, slot: usize) -> &'static [&'static str] {
+        const AHD: [[&str; 5]; 4] = [
+            ["EA1", "AT1", "HO1", "DE1", "ET1"],
+            ["EA2", "AT2", "HO2", "DE2", "ET2"],
+            ["EA3", "AT3", "HO3", "DE3", "ET3"],
+            ["EA4", "AT4", "HO4", "DE4", "ET4"],
+        ];
+        const ADSR: [[&str; 5]; 4] = [
+            ["EA1", "AT1", "DE1", "SU1", "RE1"],
+            ["EA2", "AT2", "DE2", "SU2", "RE2"],
+            ["EA3", "AT3", "DE3", "SU3", "RE3"],
+            ["EA4", "AT4", "DE4", "SU4", "RE4"],
+        ];
+        const DRUM: [[&str; 5]; 4] = [
+            ["EA1", "PK1", "BO1", "DE1", "ET1"],
+            ["EA2", "PK2", "BO2", "DE2", "ET2"],
+            ["EA3", "PK3", "BO3", "DE3", "ET3"],
+            ["EA4", "PK4", "BO4", "DE4", "ET4"],
+        ];
+        const LFO: [[&str; 4]; 4] = [
+            ["LA1", "LO1", "LT1", "LF1"],
+            ["LA2", "LO2", "LT2", "LF2"],
+            ["LA3", "LO3", "LT3", "LF3"],
+            ["LA4", "LO4", "LT4", "LF4"],
+        ];
+        const TRIG: [[&str; 5]; 4] = [
+            ["EA1", "AT1", "HO1", "DE1", "SR1"],
+            ["EA2", "AT2", "HO2", "DE2", "SR2"],
+            ["EA3", "AT3", "HO3", "DE3", "SR3"],
+            ["EA4", "AT4", "HO4", "DE4", "SR4"],
+        ];
+        const TRACKING: [[&str; 4]; 4] = [
+            ["TA1", "TS1", "TL1", "TH1"],
+            ["TA2", "TS2", "TL2", "TH2"],
+            ["TA3", "TS3", "TL3", "TH3"],
+            ["TA4", "TS4", "TL4", "TH4"],
+        ];
+        match self {
+            Mod::AHDEnv(_) => &AHD[slot],
+            Mod::ADSREnv(_) => &ADSR[slot],
+            Mod::DrumEnv(_) => &DRUM[slot],
+            Mod::LFO(_) => &LFO[slot],
+            Mod::TrigEnv(_) => &TRIG[slot],
+            Mod::TrackingEnv(_) => &TRACKING[slot],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mod::AHDEnv(_) => "AHD ENV",
+            Mod::ADSREnv(_) => "ADSR ENV",
+            Mod::DrumEnv(_) => "DRUM ENV",
+            Mod::LFO(_) => "LFO",
+            Mod::TrigEnv(_) => "TRIG ENV",
+            Mod::TrackingEnv(_) => "TRACKING ENV",
+        }
+    }
+
+    pub fn dest(&self) -> u8 {
+        match self {
+            Mod::AHDEnv(m) => m.dest,
+            Mod::ADSREnv(m) => m.dest,
+            Mod::DrumEnv(m) => m.dest,
+            Mod::LFO(m) => m.dest,
+            Mod::TrigEnv(m) => m.dest,
+            Mod::TrackingEnv(m) => m.dest,
+        }
+    }
+
+    /// The modulator as a line of its MOD page. `destinations` names the
+    /// destinations by index; one past its end is shown in hex.
+    pub fn describe(&self, destinations: &[&str]) -> String {
+        let params = match self {
+            Mod::AHDEnv(m) => format!(
+                "amt {:02x}, att {:02x}, hold {:02x}, dec {:02x}",
+                m.amount, m.attack, m.hold, m.decay
+            ),
+            Mod::ADSREnv(m) => format!(
+                "amt {:02x}, att {:02x}, dec {:02x}, sus {:02x}, rel {:02x}",
+                m.amount, m.attack, m.decay, m.sustain, m.release
+            ),
+            Mod::DrumEnv(m) => format!(
+                "amt {:02x}, peak {:02x}, body {:02x}, dec {:02x}",
+                m.amount, m.peak, m.body, m.decay
+            ),
+            Mod::LFO(m) => format!(
+                "amt {:02x}, osc {:02x}, trig {:02x}, freq {:02x}",
+                m.amount, m.shape, m.trigger_mode, m.freq
+            ),
+            Mod::TrigEnv(m) => format!(
+                "amt {:02x}, att {:02x}, hold {:02x}, dec {:02x}, src {:02x}",
+                m.amount, m.attack, m.hold, m.decay, m.src
+            ),
+            Mod::TrackingEnv(m) => format!(
+                "amt {:02x}, src {:02x}, low {:02x}, high {:02x}",
+                m.amount, m.src, m.lval, m.hval
+            ),
+        };
+        let dest = match destinations.get(self.dest() as usize) {
+            Some(name) => name.to_string(),
+            None => format!("{:02x}", self.dest()),
+        };
+        format!("{} → {}, {}", self.name(), dest, params)
+    }
+
     fn from_reader(reader: &Reader) -> Result<Self> {
         let start_pos = reader.pos();
         let first_byte = reader.read();
@@ -668,15 +1402,32 @@ impl Mod {
             3 => Mod::LFO(LFO::from_reader3(reader, dest)?),
             4 => Mod::TrigEnv(TrigEnv::from_reader(reader, dest)?),
             5 => Mod::TrackingEnv(TrackingEnv::from_reader(reader, dest)?),
-            x => panic!("Unknown mod type {}", x),
+            x => return Err(ParseError(format!("Unknown mod type {}", x))),
         };
 
-        reader.set_pos(start_pos + Self::SIZE);
+        reader.try_set_pos(start_pos + Self::SIZE)?;
         Ok(r)
     }
+
+    /// The 3.0+ encoding: the type and destination share the first byte. Bytes of
+    /// the slot a type doesn't use are left as they are.
+    pub(crate) fn write(&self, w: &mut Writer) {
+        let start = w.pos();
+        let (ty, params) = match self {
+            Mod::AHDEnv(m) => (0, vec![m.amount, m.attack, m.hold, m.decay]),
+            Mod::ADSREnv(m) => (1, vec![m.amount, m.attack, m.decay, m.sustain, m.release]),
+            Mod::DrumEnv(m) => (2, vec![m.amount, m.peak, m.body, m.decay]),
+            Mod::LFO(m) => (3, vec![m.amount, m.shape, m.trigger_mode, m.freq]),
+            Mod::TrigEnv(m) => (4, vec![m.amount, m.attack, m.hold, m.decay, m.src]),
+            Mod::TrackingEnv(m) => (5, vec![m.amount, m.src, m.lval, m.hval]),
+        };
+        w.write((ty << 4) | (self.dest() & 0x0F));
+        w.write_bytes(&params);
+        w.seek(start + Self::SIZE);
+    }
 }
 
-#[derive(PartialEq, Debug, Clone, Default)]
+#[derive(PartialEq, Debug, Clone, Default, Hash)]
 pub struct AHDEnv {
     pub dest: u8,
     pub amount: u8,
@@ -707,12 +1458,18 @@ impl AHDEnv {
         })
     }
 
-    fn to_mod(self) -> Mod {
+    /// The pre-3.0 encoding, the counterpart to `from_reader2`.
+    fn write2(&self, w: &mut Writer) {
+        w.write_bytes(&[self.dest, self.amount, self.attack, self.hold, self.decay]);
+        w.skip(1);
+    }
+
+    fn into_mod(self) -> Mod {
         Mod::AHDEnv(self)
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct LFO {
     pub shape: u8,
     pub dest: u8,
@@ -743,12 +1500,24 @@ impl LFO {
         })
     }
 
-    fn to_mod(self) -> Mod {
+    /// The pre-3.0 encoding, the counterpart to `from_reader2`.
+    fn write2(&self, w: &mut Writer) {
+        w.write_bytes(&[
+            self.shape,
+            self.dest,
+            self.trigger_mode,
+            self.freq,
+            self.amount,
+        ]);
+        w.skip(1);
+    }
+
+    fn into_mod(self) -> Mod {
         Mod::LFO(self)
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct ADSREnv {
     pub dest: u8,
     pub amount: u8,
@@ -770,7 +1539,7 @@ impl ADSREnv {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct DrumEnv {
     pub dest: u8,
     pub amount: u8,
@@ -790,7 +1559,7 @@ impl DrumEnv {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct TrigEnv {
     pub dest: u8,
     pub amount: u8,
@@ -812,7 +1581,7 @@ impl TrigEnv {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct TrackingEnv {
     pub dest: u8,
     pub amount: u8,
@@ -832,7 +1601,7 @@ impl TrackingEnv {
     }
 }
 
-#[derive(PartialEq, Debug, Default, Clone)]
+#[derive(PartialEq, Debug, Default, Clone, Hash)]
 pub struct Operator {
     pub shape: u8,
     pub ratio: u8,
@@ -844,7 +1613,19 @@ pub struct Operator {
     pub mod_b: u8,
 }
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+impl Operator {
+    /// The frequency ratio as `(whole, fine)`, shown as `whole.fine` on the device.
+    pub fn ratio(&self) -> (u8, u8) {
+        (self.ratio, self.ratio_fine)
+    }
+
+    pub fn set_ratio(&mut self, whole: u8, fine: u8) {
+        self.ratio = whole;
+        self.ratio_fine = fine;
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy, Hash)]
 pub struct ControlChange {
     pub number: u8,
     pub value: u8,
@@ -856,4 +1637,9 @@ impl ControlChange {
             value: reader.read(),
         })
     }
+
+    fn write(&self, w: &mut Writer) {
+        w.write(self.number);
+        w.write(self.value);
+    }
 }
@@ -0,0 +1,62 @@
+//! Byte layout of M8 song files, for tools that need to address the file directly.
+//!
+//! Offsets are from the start of the file, version header included. The song layout
+//! is the same for firmware 2.x and 3.x, except that scales are only present from
+//! 2.5 onward. What changes in 3.0 is the layout *within* each instrument slot.
+
+/// The `M8VERSION` header that starts every M8 file.
+pub const VERSION: usize = 0x00;
+pub const VERSION_SIZE: usize = 14;
+
+pub const DIRECTORY: usize = 0x0E;
+pub const DIRECTORY_SIZE: usize = 128;
+pub const TRANSPOSE: usize = 0x8E;
+/// Little endian `f32`.
+pub const TEMPO: usize = 0x8F;
+pub const QUANTIZE: usize = 0x93;
+pub const NAME: usize = 0x94;
+pub const NAME_SIZE: usize = 12;
+pub const MIDI_SETTINGS: usize = 0xA0;
+pub const MIDI_SETTINGS_SIZE: usize = 27;
+pub const KEY: usize = 0xBB;
+pub const MIXER_SETTINGS: usize = 0xCE;
+pub const MIXER_SETTINGS_SIZE: usize = 32;
+
+pub const GROOVES: usize = 0xEE;
+pub const GROOVE_SIZE: usize = 16;
+pub const SONG: usize = 0x2EE;
+pub const SONG_SIZE: usize = 0x800;
+pub const PHRASES: usize = 0xAEE;
+pub const PHRASE_SIZE: usize = 16 * STEP_SIZE;
+pub const STEP_SIZE: usize = 3 + 3 * FX_SIZE;
+pub const FX_SIZE: usize = 2;
+pub const CHAINS: usize = 0x9A5E;
+pub const CHAIN_SIZE: usize = 16 * CHAIN_STEP_SIZE;
+pub const CHAIN_STEP_SIZE: usize = 2;
+pub const TABLES: usize = 0xBA3E;
+pub const TABLE_SIZE: usize = 16 * TABLE_STEP_SIZE;
+pub const TABLE_STEP_SIZE: usize = 2 + 3 * FX_SIZE;
+pub const INSTRUMENTS: usize = 0x13A3E;
+pub const INSTRUMENT_SIZE: usize = 215;
+/// Follows the instruments after 3 bytes that the device doesn't appear to
+/// initialise: they differ from file to file. They aren't parsed, and writing
+/// leaves them as they are.
+pub const EFFECTS_SETTINGS: usize = 0x1A5C1;
+pub const EFFECTS_SETTINGS_SIZE: usize = 21;
+/// Preceded by 40 more uninitialised bytes after the effects settings, which
+/// are likewise left as they are.
+pub const MIDI_MAPPINGS: usize = 0x1A5FE;
+pub const MIDI_MAPPING_SIZE: usize = 7;
+/// Firmware 2.5+.
+pub const SCALES: usize = 0x1AA7E;
+pub const SCALE_SIZE: usize = 42;
+
+/// Offsets within an instrument slot.
+pub mod instrument {
+    /// NUL terminated, sampler only.
+    pub const SAMPLE_PATH: usize = 0x57;
+    pub const SAMPLE_PATH_SIZE: usize = 128;
+    /// Firmware 3.0+. All instrument kinds store their four modulators here.
+    pub const MODS: usize = 0x3F;
+    pub const MOD_SIZE: usize = 6;
+}
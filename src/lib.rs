@@ -10,28 +10,74 @@
 //! ```
 //! use m8_files::*;
 //!
+//! # #[cfg(feature = "std")] {
 //! let mut f = std::fs::File::open("./examples/songs/TEST-FILE.m8s").unwrap();
 //! let song = Song::read(&mut f).unwrap();
 //! dbg!(song);
+//! # }
 //! ```
 //!
+//! Without the default `std` feature the crate is `no_std` and only needs `alloc`:
+//! parse with the `from_bytes` methods instead of `read`.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+mod analysis;
+mod coverage;
+mod csv;
+mod diff;
+#[cfg(feature = "fs")]
+mod dir;
+mod file;
+#[cfg(feature = "test-fixtures")]
+pub mod fixtures;
 mod fx;
 mod instrument;
+pub mod layout;
+pub mod limits;
+#[cfg(feature = "midi")]
+mod midi;
+mod offset;
+mod patcher;
 mod reader;
+mod remap;
 mod scale;
+mod section;
 mod settings;
+mod stats;
 mod theme;
+mod track;
 mod version;
+mod writer;
+pub use analysis::*;
+pub use coverage::*;
+pub use diff::*;
+#[cfg(feature = "fs")]
+pub use dir::*;
+pub use file::*;
 pub use fx::*;
 pub use instrument::*;
+pub use patcher::*;
 use reader::*;
+#[cfg(feature = "std")]
+pub use reader::{M8Error, M8Result};
+pub use reader::{ParseError, Reader};
 pub use scale::*;
+pub use section::*;
 pub use settings::*;
+pub use stats::*;
 pub use theme::*;
+pub use track::*;
 pub use version::*;
+pub use writer::*;
 
-use std::fmt;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
 use arr_macro::arr;
 use byteorder::{ByteOrder, LittleEndian};
@@ -42,6 +88,7 @@ pub struct Song {
     pub directory: String,
     pub transpose: u8,
     pub tempo: f32,
+    /// The live mode quantize setting.
     pub quantize: u8,
     pub name: String,
     pub key: u8,
@@ -51,6 +98,8 @@ pub struct Song {
     pub chains: Vec<Chain>,
     pub instruments: Vec<Instrument>,
     pub tables: Vec<Table>,
+    /// Songs don't store a groove per track: every track starts on groove 0 and
+    /// only the GRV command changes it during playback.
     pub grooves: Vec<Groove>,
     pub scales: Vec<Scale>,
 
@@ -71,11 +120,14 @@ impl fmt::Debug for Song {
             .field("quantize", &self.quantize)
             .field("key", &self.key)
             .field("song", &self.song)
-            .field("chains", self.chains.get(0).unwrap_or(&Chain::default()))
-            .field("phrases", self.phrases.get(0).unwrap_or(&Phrase::default()))
+            .field("chains", self.chains.first().unwrap_or(&Chain::default()))
+            .field(
+                "phrases",
+                self.phrases.first().unwrap_or(&Phrase::default()),
+            )
             .field(
                 "instruments",
-                self.instruments.get(0).unwrap_or(&Instrument::default()),
+                self.instruments.first().unwrap_or(&Instrument::default()),
             )
             .field("tables", &self.tables[0])
             .field("grooves", &self.grooves[0])
@@ -88,42 +140,315 @@ impl fmt::Debug for Song {
 }
 
 impl Song {
-    const SIZE_PRIOR_TO_2_5: usize = 0x1A970;
-    const SIZE: usize = 0x1AD09;
-    const N_PHRASES: usize = 255;
-    const N_CHAINS: usize = 255;
-    const N_INSTRUMENTS: usize = 128;
-    const N_TABLES: usize = 256;
-    const N_GROOVES: usize = 32;
-    const N_SCALES: usize = 16;
-    const N_MIDI_MAPPINGS: usize = 128;
-
-    pub fn read(reader: &mut impl std::io::Read) -> Result<Self> {
-        let mut buf: Vec<u8> = vec![];
-        reader.read_to_end(&mut buf).unwrap();
+    const N_PHRASES: usize = limits::N_PHRASES;
+    const N_CHAINS: usize = limits::N_CHAINS;
+    const N_INSTRUMENTS: usize = limits::N_INSTRUMENTS;
+    const N_TABLES: usize = limits::N_TABLES;
+    const N_GROOVES: usize = limits::N_GROOVES;
+    const N_SCALES: usize = limits::N_SCALES;
+    const N_MIDI_MAPPINGS: usize = limits::N_MIDI_MAPPINGS;
+
+    /// A new song, as the device creates it: nothing sequenced, no instruments,
+    /// and the default grooves, scales and settings.
+    pub fn new_empty(version: Version) -> Self {
+        Self {
+            version,
+            directory: "/Songs/".to_string(),
+            transpose: 0,
+            tempo: 120.0,
+            quantize: 0,
+            name: String::new(),
+            key: 0,
+
+            song: SongSteps::default(),
+            phrases: (0..Self::N_PHRASES)
+                .map(|i| Phrase {
+                    number: i as u8,
+                    version,
+                    ..Default::default()
+                })
+                .collect(),
+            chains: (0..Self::N_CHAINS)
+                .map(|i| Chain {
+                    number: i as u8,
+                    ..Default::default()
+                })
+                .collect(),
+            instruments: vec![Instrument::None; Self::N_INSTRUMENTS],
+            tables: (0..Self::N_TABLES)
+                .map(|i| Table {
+                    number: i as u8,
+                    version,
+                    ..Default::default()
+                })
+                .collect(),
+            grooves: (0..Self::N_GROOVES)
+                .map(|i| Groove {
+                    number: i as u8,
+                    ..Default::default()
+                })
+                .collect(),
+            scales: Self::default_scales(version),
+
+            mixer_settings: MixerSettings::default(),
+            effects_settings: EffectsSettings::default(),
+            midi_settings: MidiSettings::default(),
+            midi_mappings: vec![MidiMapping::default(); Self::N_MIDI_MAPPINGS],
+        }
+    }
+
+    fn default_scales(version: Version) -> Vec<Scale> {
+        if version.at_least(2, 5) {
+            Scale::presets()
+        } else {
+            (0..Self::N_SCALES)
+                .map(|i| Scale {
+                    number: i as u8,
+                    ..Default::default()
+                })
+                .collect()
+        }
+    }
+
+    /// Longest name that fits in the file. A name that fills it isn't NUL
+    /// terminated.
+    pub const MAX_NAME_LEN: usize = layout::NAME_SIZE;
+
+    /// Longest directory that fits in the file, leaving room for the NUL terminator.
+    pub const MAX_DIRECTORY_LEN: usize = layout::DIRECTORY_SIZE - 1;
+
+    /// Set the project directory the song was saved in, e.g. `/Songs/demo/`.
+    /// Fails if it's longer than `MAX_DIRECTORY_LEN` bytes.
+    pub fn set_directory(&mut self, directory: &str) -> core::result::Result<(), String> {
+        if directory.len() > Self::MAX_DIRECTORY_LEN {
+            return Err(format!(
+                "Directory is {} bytes, the limit is {}: {}",
+                directory.len(),
+                Self::MAX_DIRECTORY_LEN,
+                directory
+            ));
+        }
+        self.directory = directory.to_string();
+        Ok(())
+    }
+
+    /// Change the firmware version the song is treated as, migrating what differs
+    /// between the two layouts. Only versions with the same major number are
+    /// supported: 2.x songs don't share an instrument or FX layout with 3.x.
+    /// Moving to 2.5+ from an earlier version adds the preset scales; moving back
+    /// fails unless the scales are untouched, since older songs can't store them.
+    pub fn set_version(&mut self, version: Version) -> core::result::Result<(), String> {
+        if version.major != self.version.major {
+            return Err(format!(
+                "Can't migrate a {} song to {}",
+                self.version, version
+            ));
+        }
+        let had_scales = self.version.at_least(2, 5);
+        let has_scales = version.at_least(2, 5);
+        if had_scales && !has_scales {
+            if self.scales != Self::default_scales(self.version) {
+                return Err(format!(
+                    "Firmware {} can't store the scales of a {} song",
+                    version, self.version
+                ));
+            }
+            self.scales = Self::default_scales(version);
+        } else if !had_scales && has_scales {
+            self.scales = Self::default_scales(version);
+        }
+
+        self.version = version;
+        for phrase in self.phrases.iter_mut() {
+            phrase.version = version;
+        }
+        for table in self.tables.iter_mut() {
+            table.version = version;
+        }
+        Ok(())
+    }
+
+    /// The length of a song file saved by firmware `version`.
+    pub fn byte_size(version: Version) -> usize {
+        if version.at_least(2, 5) {
+            layout::SCALES + Self::N_SCALES * layout::SCALE_SIZE
+        } else {
+            layout::MIDI_MAPPINGS + Self::N_MIDI_MAPPINGS * layout::MIDI_MAPPING_SIZE
+        }
+    }
+
+    /// The bytes of a new song file for firmware `version`, without needing a
+    /// file saved by the device. They parse back as [`Song::new_empty`]. Bytes
+    /// the device leaves uninitialised are zero.
+    pub fn template_bytes(version: Version) -> core::result::Result<Vec<u8>, String> {
+        let mut w = Writer::new(vec![]);
+        Self::new_empty(version).write(&mut w)?;
+        w.finish_checked(Self::byte_size(version))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes(buf)?)
+    }
+
+    /// Parse the full contents of a file that's already in memory. Fails on
+    /// firmware versions this crate doesn't support.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
+        Self::parse(buf, None, false).map(|(song, _)| song)
+    }
+
+    /// Like `read`, but ignores the version in the file's header and uses the
+    /// layout of `version` instead. See [`Song::from_bytes_with_version`].
+    #[cfg(feature = "std")]
+    pub fn read_with_version(reader: &mut impl std::io::Read, version: Version) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes_with_version(buf, version)?)
+    }
+
+    /// Like `from_bytes`, but ignores the version in the file's header and uses
+    /// the layout of `version` instead, for files whose header is wrong or
+    /// missing. The header bytes are still skipped, and the file must be long
+    /// enough for a `version` song. `version` must be supported.
+    pub fn from_bytes_with_version(buf: Vec<u8>, version: Version) -> Result<Self> {
+        Self::parse(buf, Some(version), false).map(|(song, _)| song)
+    }
+
+    /// Like `read`, but attempts files from unsupported firmware versions, and
+    /// skips instruments that can't be parsed.
+    #[cfg(feature = "std")]
+    pub fn read_lenient(reader: &mut impl std::io::Read) -> M8Result<(Self, Vec<String>)> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes_lenient(buf)?)
+    }
+
+    /// Like `from_bytes`, but files from unsupported firmware are parsed with the
+    /// closest known layout instead of failing: 3.x for newer versions, 1.x for
+    /// older ones. Instruments that fail to parse (e.g. of an unknown kind) are
+    /// left empty. Returns the song with a warning for each of these.
+    pub fn from_bytes_lenient(buf: Vec<u8>) -> Result<(Self, Vec<String>)> {
+        Self::parse(buf, None, true)
+    }
+
+    fn parse(buf: Vec<u8>, version: Option<Version>, lenient: bool) -> Result<(Self, Vec<String>)> {
         let len = buf.len();
         let reader = Reader::new(buf);
 
-        if len < Self::SIZE_PRIOR_TO_2_5 + Version::SIZE {
+        if len < Version::SIZE {
             return Err(ParseError(
                 "File is not long enough to be a M8 song".to_string(),
             ));
         }
-        let version = Version::from_reader(&reader)?;
-        if version.at_least(2, 5) && len < Self::SIZE + Version::SIZE {
-            return Err(ParseError(
-                "File is not long enough to be a M8 song".to_string(),
+        let version = match version {
+            Some(v) => {
+                reader.try_set_pos(Version::SIZE)?;
+                v
+            }
+            None => Version::from_reader(&reader)?,
+        };
+        let needed = Self::byte_size(version);
+        if len < needed {
+            return Err(ParseError(format!(
+                "File is too short for a {} song: needs {:#x} bytes, got {:#x}",
+                version, needed, len
+            )));
+        }
+
+        let mut warnings = vec![];
+        let layout = version.closest_supported();
+        if layout != version {
+            if !lenient {
+                return Err(ParseError(format!(
+                    "Unsupported firmware version {}",
+                    version
+                )));
+            }
+            warnings.push(format!(
+                "Firmware {} is not supported, parsed as {}.x",
+                version, layout.major
             ));
         }
 
-        if version.at_least(3, 0) {
-            Self::from_reader3(&reader, version)
+        let song = if layout.major == 3 {
+            Self::from_reader3(&reader, version, lenient, &mut warnings)?
         } else {
-            Self::from_reader2(&reader, version)
+            Self::from_reader2(&reader, version, lenient, &mut warnings)?
+        };
+        Ok((song, warnings))
+    }
+
+    /// Read only the instruments of a song, without parsing the rest of it. See
+    /// [`Song::instruments_from_bytes`].
+    #[cfg(feature = "std")]
+    pub fn read_instruments_only(reader: &mut impl std::io::Read) -> M8Result<Vec<Instrument>> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::instruments_from_bytes(buf)?)
+    }
+
+    /// The 128 instruments of a song file, using its version header to pick the
+    /// instrument layout. Phrases, chains, tables and the rest aren't parsed, and
+    /// the file only needs to be long enough to hold the instruments.
+    pub fn instruments_from_bytes(buf: Vec<u8>) -> Result<Vec<Instrument>> {
+        let len = buf.len();
+        let reader = Reader::new(buf);
+        if len < Version::SIZE {
+            return Err(ParseError(
+                "File is not long enough to be a M8 song".to_string(),
+            ));
+        }
+        let version = Version::from_reader(&reader)?;
+        if !version.is_supported() {
+            return Err(ParseError(format!(
+                "Unsupported firmware version {}",
+                version
+            )));
+        }
+        let needed = layout::INSTRUMENTS + Self::N_INSTRUMENTS * layout::INSTRUMENT_SIZE;
+        if len < needed {
+            return Err(ParseError(format!(
+                "File is too short for {} instruments: needs {:#x} bytes, got {:#x}",
+                version, needed, len
+            )));
         }
+        reader.try_set_pos(layout::INSTRUMENTS)?;
+        let read = if version.at_least(3, 0) {
+            Instrument::from_reader3
+        } else {
+            Instrument::from_reader2
+        };
+        Self::read_instruments(&reader, version, false, &mut vec![], read)
     }
 
-    fn from_reader2(reader: &Reader, version: Version) -> Result<Self> {
+    /// In lenient mode an instrument that fails to parse is left empty, with a
+    /// warning, and parsing carries on from the next slot.
+    fn read_instruments(
+        reader: &Reader,
+        version: Version,
+        lenient: bool,
+        warnings: &mut Vec<String>,
+        read: fn(&Reader, u8, Version) -> Result<Instrument>,
+    ) -> Result<Vec<Instrument>> {
+        (0..Self::N_INSTRUMENTS)
+            .map(|i| {
+                let start = reader.pos();
+                match read(reader, i as u8, version) {
+                    Err(ParseError(e)) if lenient => {
+                        warnings.push(format!("Instrument {:02x} was left empty: {}", i, e));
+                        reader.try_set_pos(start + layout::INSTRUMENT_SIZE)?;
+                        Ok(Instrument::None)
+                    }
+                    r => r,
+                }
+            })
+            .collect()
+    }
+
+    fn from_reader2(
+        reader: &Reader,
+        version: Version,
+        lenient: bool,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self> {
         let directory = reader.read_string(128);
         let transpose = reader.read();
         let tempo = LittleEndian::read_f32(reader.read_bytes(4));
@@ -135,41 +460,31 @@ impl Song {
         let mixer_settings = MixerSettings::from_reader(reader)?;
         // println!("{:x}", reader.pos());
 
-        let grooves = (0..Self::N_GROOVES)
-            .map(|i| Groove::from_reader(reader, i as u8))
-            .collect::<Result<Vec<Groove>>>()?;
+        let grooves = read_sections(reader, layout::GROOVES, Self::N_GROOVES, version)?;
         let song = SongSteps::from_reader(reader)?;
-        let phrases = (0..Self::N_PHRASES)
-            .map(|i| Phrase::from_reader(reader, i as u8, version))
-            .collect::<Result<Vec<Phrase>>>()?;
-        let chains = (0..Self::N_CHAINS)
-            .map(|i| Chain::from_reader(reader, i as u8))
-            .collect::<Result<Vec<Chain>>>()?;
-        let tables = (0..Self::N_TABLES)
-            .map(|i| Table::from_reader(reader, i as u8, version))
-            .collect::<Result<Vec<Table>>>()?;
-        let instruments = (0..Self::N_INSTRUMENTS)
-            .map(|i| Instrument::from_reader2(reader, i as u8, version))
-            .collect::<Result<Vec<Instrument>>>()?;
-
-        reader.read_bytes(3); // Skip
+        let phrases = read_sections(reader, layout::PHRASES, Self::N_PHRASES, version)?;
+        let chains = read_sections(reader, layout::CHAINS, Self::N_CHAINS, version)?;
+        let tables = read_sections(reader, layout::TABLES, Self::N_TABLES, version)?;
+        let instruments =
+            Self::read_instruments(reader, version, lenient, warnings, Instrument::from_reader2)?;
+
+        reader.try_set_pos(layout::EFFECTS_SETTINGS)?;
         let effects_settings = EffectsSettings::from_reader(reader)?;
-        reader.set_pos(0x1A5FE);
+        reader.try_set_pos(layout::MIDI_MAPPINGS)?;
         let midi_mappings = (0..Self::N_MIDI_MAPPINGS)
             .map(|_| MidiMapping::from_reader(reader))
             .collect::<Result<Vec<MidiMapping>>>()?;
 
         let scales: Vec<Scale> = if version.at_least(2, 5) {
-            reader.set_pos(0x1AA7E);
+            reader.try_set_pos(layout::SCALES)?;
             (0..Self::N_SCALES)
                 .map(|i| Scale::from_reader(reader, i as u8))
                 .collect::<Result<Vec<Scale>>>()?
         } else {
             (0..Self::N_SCALES)
-                .map(|i| -> Scale {
-                    let mut s = Scale::default();
-                    s.number = i as u8;
-                    s
+                .map(|i| Scale {
+                    number: i as u8,
+                    ..Default::default()
                 })
                 .collect()
         };
@@ -196,7 +511,12 @@ impl Song {
         })
     }
 
-    fn from_reader3(reader: &Reader, version: Version) -> Result<Self> {
+    fn from_reader3(
+        reader: &Reader,
+        version: Version,
+        lenient: bool,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self> {
         // TODO read groove, scale
         let directory = reader.read_string(128);
         let transpose = reader.read();
@@ -209,41 +529,31 @@ impl Song {
         let mixer_settings = MixerSettings::from_reader(reader)?;
         // println!("{:x}", reader.pos());
 
-        let grooves = (0..Self::N_GROOVES)
-            .map(|i| Groove::from_reader(reader, i as u8))
-            .collect::<Result<Vec<Groove>>>()?;
+        let grooves = read_sections(reader, layout::GROOVES, Self::N_GROOVES, version)?;
         let song = SongSteps::from_reader(reader)?;
-        let phrases = (0..Self::N_PHRASES)
-            .map(|i| Phrase::from_reader(reader, i as u8, version))
-            .collect::<Result<Vec<Phrase>>>()?;
-        let chains = (0..Self::N_CHAINS)
-            .map(|i| Chain::from_reader(reader, i as u8))
-            .collect::<Result<Vec<Chain>>>()?;
-        let tables = (0..Self::N_TABLES)
-            .map(|i| Table::from_reader(reader, i as u8, version))
-            .collect::<Result<Vec<Table>>>()?;
-        let instruments = (0..Self::N_INSTRUMENTS)
-            .map(|i| Instrument::from_reader3(reader, i as u8, version))
-            .collect::<Result<Vec<Instrument>>>()?;
-
-        reader.read_bytes(3); // Skip
+        let phrases = read_sections(reader, layout::PHRASES, Self::N_PHRASES, version)?;
+        let chains = read_sections(reader, layout::CHAINS, Self::N_CHAINS, version)?;
+        let tables = read_sections(reader, layout::TABLES, Self::N_TABLES, version)?;
+        let instruments =
+            Self::read_instruments(reader, version, lenient, warnings, Instrument::from_reader3)?;
+
+        reader.try_set_pos(layout::EFFECTS_SETTINGS)?;
         let effects_settings = EffectsSettings::from_reader(reader)?;
-        reader.set_pos(0x1A5FE);
+        reader.try_set_pos(layout::MIDI_MAPPINGS)?;
         let midi_mappings = (0..Self::N_MIDI_MAPPINGS)
             .map(|_| MidiMapping::from_reader(reader))
             .collect::<Result<Vec<MidiMapping>>>()?;
 
         let scales: Vec<Scale> = if version.at_least(2, 5) {
-            reader.set_pos(0x1AA7E);
+            reader.try_set_pos(layout::SCALES)?;
             (0..Self::N_SCALES)
                 .map(|i| Scale::from_reader(reader, i as u8))
                 .collect::<Result<Vec<Scale>>>()?
         } else {
             (0..Self::N_SCALES)
-                .map(|i| -> Scale {
-                    let mut s = Scale::default();
-                    s.number = i as u8;
-                    s
+                .map(|i| Scale {
+                    number: i as u8,
+                    ..Default::default()
                 })
                 .collect()
         };
@@ -269,9 +579,406 @@ impl Song {
             midi_mappings,
         })
     }
+
+    /// Check that every slot list has as many entries as the file stores, which
+    /// writing relies on. Songs that are parsed or built with `new_empty` always
+    /// do.
+    pub fn validate_shape(&self) -> core::result::Result<(), String> {
+        let check = |what: &str, len: usize, expected: usize| {
+            if len == expected {
+                Ok(())
+            } else {
+                Err(format!("Song has {} {}, expected {}", len, what, expected))
+            }
+        };
+        check("phrases", self.phrases.len(), Self::N_PHRASES)?;
+        check("chains", self.chains.len(), Self::N_CHAINS)?;
+        check("instruments", self.instruments.len(), Self::N_INSTRUMENTS)?;
+        check("tables", self.tables.len(), Self::N_TABLES)?;
+        check("grooves", self.grooves.len(), Self::N_GROOVES)?;
+        check("scales", self.scales.len(), Self::N_SCALES)?;
+        check(
+            "MIDI mappings",
+            self.midi_mappings.len(),
+            Self::N_MIDI_MAPPINGS,
+        )
+    }
+
+    /// Write the song over `w`, which should hold the bytes of a song file (the
+    /// file this song was read from, or a template). Sections this crate doesn't
+    /// parse are left as they are in the buffer.
+    ///
+    /// Same as [`Song::write_with`] targeting the song's own version.
+    pub fn write(&self, w: &mut Writer) -> core::result::Result<(), String> {
+        self.write_with(
+            WriteOptions {
+                version: self.version,
+            },
+            w,
+        )
+    }
+
+    /// Write the song as a file for firmware `opts.version`. The target must be
+    /// supported and share the song's major version, since 2.x and 3.x lay out
+    /// instruments differently. A non-empty buffer must be the size of a file
+    /// for the target version. Nothing is written if an instrument can't be
+    /// stored in the target version, see [`Instrument::check_writable`].
+    pub fn write_with(
+        &self,
+        opts: WriteOptions,
+        w: &mut Writer,
+    ) -> core::result::Result<(), String> {
+        self.validate_shape()?;
+        if self.name.len() > Self::MAX_NAME_LEN {
+            return Err(format!(
+                "Name is {} bytes, the limit is {}",
+                self.name.len(),
+                Self::MAX_NAME_LEN
+            ));
+        }
+        if self.directory.len() > Self::MAX_DIRECTORY_LEN {
+            return Err(format!(
+                "Directory is {} bytes, the limit is {}",
+                self.directory.len(),
+                Self::MAX_DIRECTORY_LEN
+            ));
+        }
+        let version = opts.version;
+        if !version.is_supported() {
+            return Err(format!("Can't write songs for firmware {}", version));
+        }
+        if version.major != self.version.major {
+            return Err(format!(
+                "Can't write a {} song as {}",
+                self.version, version
+            ));
+        }
+        for instrument in self.instruments.iter() {
+            instrument.check_writable(version)?;
+        }
+        let size = Self::byte_size(version);
+        if !w.is_empty() && w.len() != size {
+            return Err(format!(
+                "Buffer is {:#x} bytes, a {} song needs {:#x}",
+                w.len(),
+                version,
+                size
+            ));
+        }
+
+        w.seek(layout::VERSION);
+        version.write(w);
+        w.write_string(&self.directory, layout::DIRECTORY_SIZE);
+        w.write(self.transpose);
+        w.write_bytes(&self.tempo.to_le_bytes());
+        w.write(self.quantize);
+        w.write_string(&self.name, layout::NAME_SIZE);
+        self.midi_settings.write(w);
+        w.write(self.key);
+        w.seek(layout::MIXER_SETTINGS);
+        self.mixer_settings.write(w);
+        write_sections(w, layout::GROOVES, &self.grooves);
+        w.seek(layout::SONG);
+        w.write_bytes(&self.song.steps);
+        write_sections(w, layout::PHRASES, &self.phrases);
+        write_sections(w, layout::CHAINS, &self.chains);
+        write_sections(w, layout::TABLES, &self.tables);
+        w.seek(layout::INSTRUMENTS);
+        for instrument in self.instruments.iter() {
+            instrument.write(w, version)?;
+        }
+
+        w.seek(layout::EFFECTS_SETTINGS);
+        self.effects_settings.write(w);
+        w.seek(layout::MIDI_MAPPINGS);
+        for mapping in self.midi_mappings.iter() {
+            mapping.write(w);
+        }
+        if version.at_least(2, 5) {
+            w.seek(layout::SCALES);
+            for scale in self.scales.iter() {
+                scale.write(w);
+            }
+        }
+        Ok(())
+    }
+
+    /// Transpose the notes of every phrase. Chain transposes are left untouched.
+    pub fn transpose_all(&mut self, semitones: i8) {
+        for phrase in self.phrases.iter_mut() {
+            phrase.transpose(semitones);
+        }
+    }
+
+    /// Apply [`Phrase::set_all_velocities`] to every phrase referenced by a chain.
+    pub fn set_all_velocities(&mut self, velocity: u8) {
+        for i in 0..Self::N_PHRASES {
+            if self.phrase_is_referenced(i) {
+                self.phrases[i].set_all_velocities(velocity);
+            }
+        }
+    }
+
+    /// Apply [`Phrase::scale_velocities`] to every phrase referenced by a chain.
+    pub fn scale_velocities(&mut self, factor: f32) {
+        for i in 0..Self::N_PHRASES {
+            if self.phrase_is_referenced(i) {
+                self.phrases[i].scale_velocities(factor);
+            }
+        }
+    }
+
+    pub fn instruments_of_kind(
+        &self,
+        kind: InstrumentKind,
+    ) -> impl Iterator<Item = (usize, &Instrument)> {
+        self.instruments
+            .iter()
+            .enumerate()
+            .filter(move |(_, i)| i.kind() == Some(kind))
+    }
+
+    pub fn samplers(&self) -> impl Iterator<Item = (usize, &Sampler)> {
+        self.instruments
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, i)| match i {
+                Instrument::Sampler(s) => Some((ix, s)),
+                _ => None,
+            })
+    }
+
+    pub fn fm_synths(&self) -> impl Iterator<Item = (usize, &FMSynth)> {
+        self.instruments
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, i)| match i {
+                Instrument::FMSynth(s) => Some((ix, s)),
+                _ => None,
+            })
+    }
+
+    /// The sample path of every sampler that has one, with its slot.
+    pub fn sample_paths(&self) -> Vec<(usize, &str)> {
+        self.samplers()
+            .filter(|(_, s)| !s.sample_path.is_empty())
+            .map(|(ix, s)| (ix, s.sample_path.as_str()))
+            .collect()
+    }
+
+    /// Like `sample_paths`, but each path only once, in slot order.
+    pub fn unique_sample_paths(&self) -> Vec<&str> {
+        let mut paths: Vec<&str> = vec![];
+        for (_, path) in self.sample_paths() {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// Rewrite every sampler's path through `f`, leaving it alone when `f` returns
+    /// `None`. Nothing is changed if any new path is too long.
+    pub fn remap_sample_paths(
+        &mut self,
+        f: impl Fn(&str) -> Option<String>,
+    ) -> core::result::Result<(), String> {
+        let mut remapped = vec![];
+        for (ix, path) in self.sample_paths() {
+            if let Some(new_path) = f(path) {
+                if new_path.len() > Sampler::MAX_SAMPLE_PATH_LEN {
+                    return Err(format!(
+                        "Instrument {:02x}: sample path is {} bytes, the limit is {}: {}",
+                        ix,
+                        new_path.len(),
+                        Sampler::MAX_SAMPLE_PATH_LEN,
+                        new_path
+                    ));
+                }
+                remapped.push((ix, new_path));
+            }
+        }
+        for (ix, path) in remapped {
+            if let Instrument::Sampler(s) = &mut self.instruments[ix] {
+                s.set_sample_path(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The song key, stored as a note number.
+    pub fn key_note(&self) -> Note {
+        Note(self.key)
+    }
+
+    pub fn set_key(&mut self, key: Note) {
+        self.key = key.0;
+    }
+
+    /// The song transpose, stored as a two's complement semitone count.
+    pub fn transpose_semitones(&self) -> i8 {
+        self.transpose as i8
+    }
+
+    pub fn set_transpose_semitones(&mut self, semitones: i8) {
+        self.transpose = semitones as u8;
+    }
+
+    /// Fails if `ix` is out of range.
+    pub fn clear_instrument(&mut self, ix: usize) -> core::result::Result<(), String> {
+        remap::check_range("Instrument", ix, Self::N_INSTRUMENTS)?;
+        self.instruments[ix].clear();
+        Ok(())
+    }
+
+    /// Fails if `ix` is out of range.
+    pub fn clear_chain(&mut self, ix: usize) -> core::result::Result<(), String> {
+        remap::check_range("Chain", ix, Self::N_CHAINS)?;
+        self.chains[ix].clear();
+        Ok(())
+    }
+
+    /// Fails if `ix` is out of range.
+    pub fn clear_phrase(&mut self, ix: usize) -> core::result::Result<(), String> {
+        remap::check_range("Phrase", ix, Self::N_PHRASES)?;
+        self.phrases[ix].clear();
+        Ok(())
+    }
+
+    /// Fails if `ix` is out of range.
+    pub fn clear_table(&mut self, ix: usize) -> core::result::Result<(), String> {
+        remap::check_range("Table", ix, Self::N_TABLES)?;
+        self.tables[ix].clear();
+        Ok(())
+    }
+
+    pub fn clear_all_instruments(&mut self) {
+        self.instruments.iter_mut().for_each(Instrument::clear);
+    }
+
+    pub fn clear_all_chains(&mut self) {
+        self.chains.iter_mut().for_each(Chain::clear);
+    }
+
+    pub fn clear_all_phrases(&mut self) {
+        self.phrases.iter_mut().for_each(Phrase::clear);
+    }
+
+    pub fn clear_all_tables(&mut self) {
+        self.tables.iter_mut().for_each(Table::clear);
+    }
+
+    /// Every instrument owns the table with the same index.
+    pub fn instrument_table(&self, ix: usize) -> &Table {
+        &self.tables[ix]
+    }
+
+    pub fn instrument_table_mut(&mut self, ix: usize) -> &mut Table {
+        &mut self.tables[ix]
+    }
+
+    pub fn instrument_has_table(&self, ix: usize) -> bool {
+        !self.tables[ix].is_empty()
+    }
+
+    /// Copy instrument `src_ix` of `src` into slot `dest_ix` of this song. Phrases,
+    /// chains and tables are left alone. Fails if `dest_ix` already holds an
+    /// instrument, unless `overwrite` is set, or if this song's firmware version
+    /// can't store the instrument (see [`Instrument::check_writable`]).
+    pub fn import_instrument(
+        &mut self,
+        src: &Song,
+        src_ix: usize,
+        dest_ix: usize,
+        overwrite: bool,
+    ) -> core::result::Result<(), String> {
+        let instrument = src
+            .instruments
+            .get(src_ix)
+            .ok_or_else(|| format!("Instrument {:02x} is out of range", src_ix))?;
+        if *instrument == Instrument::None {
+            return Err(format!("Instrument {:02x} is empty", src_ix));
+        }
+        if dest_ix >= Self::N_INSTRUMENTS {
+            return Err(format!("Instrument {:02x} is out of range", dest_ix));
+        }
+        if !overwrite && self.instruments[dest_ix] != Instrument::None {
+            return Err(format!("Instrument {:02x} is already in use", dest_ix));
+        }
+        instrument.check_writable(self.version)?;
+
+        let mut instrument = instrument.clone();
+        instrument.set_number(dest_ix as u8);
+        self.instruments[dest_ix] = instrument;
+        Ok(())
+    }
+
+    /// Copy `chain` into the first free chain slot, returning the new chain's number.
+    /// When `deep` is set, the phrases it references are copied into free phrase
+    /// slots as well, so the new chain can be edited without affecting the original.
+    pub fn duplicate_chain(&mut self, chain: u8, deep: bool) -> core::result::Result<u8, String> {
+        if chain as usize >= Self::N_CHAINS {
+            return Err(format!("Chain {:02x} is out of range", chain));
+        }
+        let to = (0..Self::N_CHAINS)
+            .find(|&i| i != chain as usize && self.chain_is_free(i))
+            .ok_or_else(|| "No free chain slot".to_string())?;
+
+        let mut new_chain = self.chains[chain as usize].clone();
+        new_chain.number = to as u8;
+
+        if deep {
+            let mut from_phrases: Vec<u8> = vec![];
+            for step in new_chain.steps.iter().filter(|s| !s.is_empty()) {
+                if !from_phrases.contains(&step.phrase) {
+                    from_phrases.push(step.phrase);
+                }
+            }
+            let to_phrases: Vec<usize> = (0..Self::N_PHRASES)
+                .filter(|&i| self.phrase_is_free(i))
+                .take(from_phrases.len())
+                .collect();
+            if to_phrases.len() < from_phrases.len() {
+                return Err("Not enough free phrase slots".to_string());
+            }
+
+            for (&from, &to) in from_phrases.iter().zip(to_phrases.iter()) {
+                let mut phrase = self.phrases[from as usize].clone();
+                phrase.number = to as u8;
+                self.phrases[to] = phrase;
+                for step in new_chain.steps.iter_mut().filter(|s| s.phrase == from) {
+                    step.phrase = to as u8;
+                }
+            }
+        }
+
+        self.chains[to] = new_chain;
+        Ok(to as u8)
+    }
+
+    fn chain_is_free(&self, chain: usize) -> bool {
+        self.chains[chain].is_empty() && !self.song.steps.contains(&(chain as u8))
+    }
+
+    fn phrase_is_free(&self, phrase: usize) -> bool {
+        self.phrases[phrase].is_empty() && !self.phrase_is_referenced(phrase)
+    }
+
+    fn phrase_is_referenced(&self, phrase: usize) -> bool {
+        self.chains
+            .iter()
+            .any(|c| c.steps.iter().any(|s| s.phrase == phrase as u8))
+    }
 }
 
-#[derive(PartialEq, Clone)]
+/// How [`Song::write_with`] lays out the file.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct WriteOptions {
+    /// The firmware version to write the song for.
+    pub version: Version,
+}
+
+#[derive(PartialEq, Clone, Hash)]
 pub struct SongSteps {
     pub steps: [u8; 2048],
 }
@@ -291,7 +998,7 @@ impl SongSteps {
         (start..start + 8).fold(format!("{row:02x} "), |s, b| -> String {
             let v = self.steps[b];
             let repr = if v == 255 {
-                format!("-- ")
+                "-- ".to_string()
             } else {
                 format!("{:02x} ", v)
             };
@@ -301,11 +1008,17 @@ impl SongSteps {
 
     fn from_reader(reader: &Reader) -> Result<Self> {
         Ok(Self {
-            steps: reader.read_bytes(2048).try_into().unwrap(),
+            steps: reader.read_array()?,
         })
     }
 }
 
+impl Default for SongSteps {
+    fn default() -> Self {
+        Self { steps: [255; 2048] }
+    }
+}
+
 impl fmt::Display for SongSteps {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "SONG\n\n{}", self.print_screen())
@@ -317,14 +1030,68 @@ impl fmt::Debug for SongSteps {
     }
 }
 
-#[derive(PartialEq, Clone, Default)]
+#[derive(PartialEq, Clone, Default, Hash)]
 pub struct Chain {
     pub number: u8,
     pub steps: [ChainStep; 16],
 }
 impl Chain {
+    /// Empty every step, keeping the chain's number.
+    pub fn clear(&mut self) {
+        self.steps = Default::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.iter().all(ChainStep::is_empty)
+    }
+
+    /// Insert `step` at `pos`, shifting the steps after it down. Fails if the
+    /// last step isn't empty, since it would be pushed out.
+    pub fn insert_step(&mut self, pos: usize, step: ChainStep) -> core::result::Result<(), String> {
+        insert_step(&mut self.steps, pos, step, ChainStep::is_empty)
+            .map_err(|e| format!("Chain {:02x}: {}", self.number, e))
+    }
+
+    /// Remove the step at `pos`, shifting the steps after it up and leaving an
+    /// empty step at the end. `None` if `pos` is out of range.
+    pub fn remove_step(&mut self, pos: usize) -> Option<ChainStep> {
+        remove_step(&mut self.steps, pos)
+    }
+
+    /// Shift the transpose of every non-empty step, saturating at the signed range.
+    pub fn transpose(&mut self, semitones: i8) {
+        for step in self.steps.iter_mut().filter(|s| !s.is_empty()) {
+            step.transpose = (step.transpose as i8).saturating_add(semitones) as u8;
+        }
+    }
+
+    /// What the chain plays: the phrase of each non-empty step, in order, with its
+    /// notes transposed by the step's transpose as [`Phrase::transpose`] does.
+    /// `phrases` is indexed by phrase number, e.g. `&song.phrases`. Steps whose
+    /// phrase isn't in it are skipped.
+    pub fn resolved_steps(&self, phrases: &[Phrase]) -> Vec<Phrase> {
+        self.steps
+            .iter()
+            .filter(|step| !step.is_empty())
+            .filter_map(|step| {
+                let mut phrase = phrases.get(step.phrase as usize)?.clone();
+                phrase.transpose(step.transpose as i8);
+                Some(phrase)
+            })
+            .collect()
+    }
+
     pub fn print_screen(&self) -> String {
-        (0..16).fold("  PH TSP\n".to_string(), |s, row| {
+        self.print_rows(16)
+    }
+
+    /// Like `print_screen`, but stops after the last non-empty step.
+    pub fn print_compact(&self) -> String {
+        self.print_rows(used_steps(&self.steps, ChainStep::is_empty))
+    }
+
+    fn print_rows(&self, n: usize) -> String {
+        (0..n).fold("  PH TSP\n".to_string(), |s, row| {
             s + &self.steps[row].print(row as u8) + "\n"
         })
     }
@@ -335,6 +1102,12 @@ impl Chain {
             steps: arr![ChainStep::from_reader(reader)?; 16],
         })
     }
+
+    fn write(&self, w: &mut Writer) {
+        for step in &self.steps {
+            step.write(w);
+        }
+    }
 }
 
 impl fmt::Display for Chain {
@@ -348,7 +1121,7 @@ impl fmt::Debug for Chain {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct ChainStep {
     pub phrase: u8,
     pub transpose: u8,
@@ -362,6 +1135,10 @@ impl Default for ChainStep {
     }
 }
 impl ChainStep {
+    pub fn is_empty(&self) -> bool {
+        self.phrase == 255
+    }
+
     pub fn print(&self, row: u8) -> String {
         if self.phrase == 255 {
             format!("{:x} -- 00", row)
@@ -376,66 +1153,294 @@ impl ChainStep {
             transpose: reader.read(),
         })
     }
+
+    fn write(&self, w: &mut Writer) {
+        w.write(self.phrase);
+        w.write(self.transpose);
+    }
 }
 
-#[derive(PartialEq, Clone, Default)]
-pub struct Phrase {
-    pub number: u8,
-    pub steps: [Step; 16],
-    version: Version,
+/// The number of steps up to and including the last non-empty one.
+fn used_steps<T>(steps: &[T], is_empty: fn(&T) -> bool) -> usize {
+    steps
+        .iter()
+        .rposition(|s| !is_empty(s))
+        .map_or(0, |i| i + 1)
 }
-impl Phrase {
-    pub fn print_screen(&self) -> String {
-        (0..16).fold("  N   V  I  FX1   FX2   FX3  \n".to_string(), |s, row| {
-            s + &self.steps[row].print(row as u8, self.version) + "\n"
-        })
-    }
 
-    fn from_reader(reader: &Reader, number: u8, version: Version) -> Result<Self> {
-        Ok(Self {
-            number,
-            steps: arr![Step::from_reader(reader)?; 16],
-            version,
-        })
+fn insert_step<T>(
+    steps: &mut [T],
+    pos: usize,
+    step: T,
+    is_empty: fn(&T) -> bool,
+) -> core::result::Result<(), String> {
+    if pos >= steps.len() {
+        return Err(format!("Step {:x} is out of range", pos));
+    }
+    if !steps.last().is_some_and(is_empty) {
+        return Err("The last step isn't empty".to_string());
     }
+    steps[pos..].rotate_right(1);
+    steps[pos] = step;
+    Ok(())
 }
 
-impl fmt::Display for Phrase {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "PHRASE {:02x}\n\n{}", self.number, self.print_screen())
+fn remove_step<T: Default>(steps: &mut [T], pos: usize) -> Option<T> {
+    if pos >= steps.len() {
+        return None;
     }
+    let removed = core::mem::take(&mut steps[pos]);
+    steps[pos..].rotate_left(1);
+    Some(removed)
 }
-impl fmt::Debug for Phrase {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", &self)
+
+/// Each FX column's command label and value, `None` where the column is empty.
+fn fx_cells<'a>(fx: impl Iterator<Item = &'a FX>, version: Version) -> [Option<(String, u8)>; 3] {
+    let mut cells = [None, None, None];
+    for (cell, fx) in cells.iter_mut().zip(fx) {
+        if !fx.is_empty() {
+            *cell = Some((fx.label(version), fx.value));
+        }
     }
+    cells
 }
 
+/// One row of [`Phrase::rows`]. Empty fields are `None`.
 #[derive(PartialEq, Debug, Clone, Default)]
-pub struct Step {
-    pub note: Note,
+pub struct PhraseRow {
+    pub note: Option<Note>,
+    pub velocity: Option<u8>,
+    pub instrument: Option<u8>,
+    pub fx: [Option<(String, u8)>; 3],
+}
+
+/// One row of [`Table::rows`]. Table transposes have no empty value.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct TableRow {
+    pub transpose: i8,
+    pub velocity: Option<u8>,
+    pub fx: [Option<(String, u8)>; 3],
+}
+
+#[derive(PartialEq, Clone, Default, Hash)]
+pub struct Phrase {
+    pub number: u8,
+    pub steps: [Step; 16],
+    version: Version,
+}
+impl Phrase {
+    /// Empty every step, keeping the phrase's number.
+    pub fn clear(&mut self) {
+        self.steps = Default::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.iter().all(Step::is_empty)
+    }
+
+    /// Insert `step` at `pos`, shifting the steps after it down. Fails if the
+    /// last step isn't empty, since it would be pushed out.
+    pub fn insert_step(&mut self, pos: usize, step: Step) -> core::result::Result<(), String> {
+        insert_step(&mut self.steps, pos, step, Step::is_empty)
+            .map_err(|e| format!("Phrase {:02x}: {}", self.number, e))
+    }
+
+    /// Remove the step at `pos`, shifting the steps after it up and leaving an
+    /// empty step at the end. `None` if `pos` is out of range.
+    pub fn remove_step(&mut self, pos: usize) -> Option<Step> {
+        remove_step(&mut self.steps, pos)
+    }
+
+    /// Shift every note, clamping to `Note::MAX`. Empty and note-off steps are
+    /// unchanged.
+    pub fn transpose(&mut self, semitones: i8) {
+        for step in self.steps.iter_mut() {
+            step.note = step.note.transpose(semitones);
+        }
+    }
+
+    /// Set the velocity of every step that has one, clamping to `0..=0x7F`.
+    /// Empty velocities stay empty.
+    pub fn set_all_velocities(&mut self, velocity: u8) {
+        for step in self.steps.iter_mut().filter(|s| s.velocity != 255) {
+            step.velocity = velocity.min(0x7F);
+        }
+    }
+
+    /// Multiply the velocity of every step that has one, clamping to `0..=0x7F`.
+    pub fn scale_velocities(&mut self, factor: f32) {
+        for step in self.steps.iter_mut().filter(|s| s.velocity != 255) {
+            // Rounds half up; `f32::round` isn't available without std
+            step.velocity = ((step.velocity as f32 * factor).clamp(0.0, 127.0) + 0.5) as u8;
+        }
+    }
+
+    /// The phrase screen as data rather than text.
+    pub fn rows(&self) -> [PhraseRow; 16] {
+        core::array::from_fn(|i| {
+            let step = &self.steps[i];
+            PhraseRow {
+                note: (!step.note.is_empty()).then_some(step.note),
+                velocity: (step.velocity != 255).then_some(step.velocity),
+                instrument: (step.instrument != 255).then_some(step.instrument),
+                fx: fx_cells(step.fx_iter(), self.version),
+            }
+        })
+    }
+
+    pub fn print_screen(&self) -> String {
+        self.print_rows(16)
+    }
+
+    /// Like `print_screen`, but stops after the last non-empty step.
+    pub fn print_compact(&self) -> String {
+        self.print_rows(used_steps(&self.steps, Step::is_empty))
+    }
+
+    fn print_rows(&self, n: usize) -> String {
+        (0..n).fold("  N   V  I  FX1   FX2   FX3  \n".to_string(), |s, row| {
+            s + &self.steps[row].print(row as u8, self.version) + "\n"
+        })
+    }
+
+    fn from_reader(reader: &Reader, number: u8, version: Version) -> Result<Self> {
+        Ok(Self {
+            number,
+            steps: arr![Step::from_reader(reader)?; 16],
+            version,
+        })
+    }
+
+    fn write(&self, w: &mut Writer) {
+        for step in &self.steps {
+            step.write(w);
+        }
+    }
+}
+
+impl fmt::Display for Phrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PHRASE {:02x}\n\n{}", self.number, self.print_screen())
+    }
+}
+impl fmt::Debug for Phrase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &self)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Hash)]
+pub struct Step {
+    pub note: Note,
     pub velocity: u8,
     pub instrument: u8,
     pub fx1: FX,
     pub fx2: FX,
     pub fx3: FX,
 }
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            note: Note::default(),
+            velocity: 255,
+            instrument: 255,
+            fx1: FX::default(),
+            fx2: FX::default(),
+            fx3: FX::default(),
+        }
+    }
+}
 impl Step {
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// `None` writes the empty note.
+    pub fn set_note(&mut self, note: Option<Note>) {
+        self.note = note.unwrap_or_default();
+    }
+
+    /// `None` writes the empty (`--`) velocity.
+    pub fn set_velocity(&mut self, velocity: Option<u8>) {
+        self.velocity = velocity.unwrap_or(255);
+    }
+
+    /// `None` writes the empty (`--`) instrument.
+    pub fn set_instrument(&mut self, instrument: Option<u8>) {
+        self.instrument = instrument.unwrap_or(255);
+    }
+
+    /// Set FX column `slot` (0-2). `None` writes the empty (`---00`) command.
+    /// Fails past the last column.
+    pub fn set_fx(&mut self, slot: usize, fx: Option<FX>) -> core::result::Result<(), String> {
+        let column = self
+            .fx_mut(slot)
+            .ok_or_else(|| format!("FX slot {} is out of range", slot))?;
+        *column = fx.unwrap_or_default();
+        Ok(())
+    }
+
+    /// Whether FX column `slot` holds an instrument command, which is interpreted
+    /// by the step's instrument rather than the sequencer. False past the last
+    /// column.
+    pub fn fx_is_instrument_command(&self, slot: usize) -> bool {
+        self.fx(slot).is_some_and(FX::is_instrument_command)
+    }
+
+    /// The label of FX column `slot`, or `None` past the last.
+    pub fn fx_label(&self, slot: usize, version: Version) -> Option<String> {
+        self.fx(slot).map(|fx| fx.label(version))
+    }
+
+    /// FX column `slot` (0-2), or `None` past the last.
+    pub fn fx(&self, slot: usize) -> Option<&FX> {
+        match slot {
+            0 => Some(&self.fx1),
+            1 => Some(&self.fx2),
+            2 => Some(&self.fx3),
+            _ => None,
+        }
+    }
+
+    pub fn fx_mut(&mut self, slot: usize) -> Option<&mut FX> {
+        match slot {
+            0 => Some(&mut self.fx1),
+            1 => Some(&mut self.fx2),
+            2 => Some(&mut self.fx3),
+            _ => None,
+        }
+    }
+
+    pub fn fx_iter(&self) -> impl Iterator<Item = &FX> {
+        [&self.fx1, &self.fx2, &self.fx3].into_iter()
+    }
+
+    pub fn fx_iter_mut(&mut self) -> impl Iterator<Item = &mut FX> {
+        [&mut self.fx1, &mut self.fx2, &mut self.fx3].into_iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.note.0 == 255
+            && self.velocity == 255
+            && self.instrument == 255
+            && self.fx_iter().all(FX::is_empty)
+    }
+
     pub fn print(&self, row: u8, version: Version) -> String {
         let velocity = if self.velocity == 255 {
-            format!("--")
+            "--".to_string()
         } else {
             format!("{:02x}", self.velocity)
         };
         let instrument = if self.instrument == 255 {
-            format!("--")
+            "--".to_string()
         } else {
             format!("{:02x}", self.instrument)
         };
         format!(
             "{:x} {} {} {} {} {} {}",
             row,
-            self.note,
+            self.note.display(version),
             velocity,
             instrument,
             self.fx1.print(version),
@@ -454,22 +1459,93 @@ impl Step {
             fx3: FX::from_reader(reader)?,
         })
     }
+
+    fn write(&self, w: &mut Writer) {
+        w.write(self.note.0);
+        w.write(self.velocity);
+        w.write(self.instrument);
+        self.fx_iter().for_each(|fx| fx.write(w));
+    }
 }
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Debug, Clone, Copy, Hash)]
 pub struct Note(pub u8);
 impl Default for Note {
     fn default() -> Self {
         Note(255)
     }
 }
+impl Note {
+    pub const MAX: u8 = 0x7F;
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 255
+    }
+
+    pub fn is_off(&self) -> bool {
+        !self.is_empty() && self.0 > Self::MAX
+    }
+
+    /// Empty and note-off values are returned unchanged. Others are clamped to `0..=MAX`.
+    pub fn transpose(self, semitones: i8) -> Self {
+        if self.is_empty() || self.is_off() {
+            self
+        } else {
+            Note((self.0 as i16 + semitones as i16).clamp(0, Self::MAX as i16) as u8)
+        }
+    }
+
+    /// The note as shown for firmware `version`. `Display` shows values above
+    /// `MAX` as note-off (`OFF`), which is how 3.0+ encodes it. The encoding
+    /// before 3.0 isn't known, so for those versions such values are shown as
+    /// their hex byte (e.g. `80 `) rather than guessed at.
+    pub fn display(&self, version: Version) -> String {
+        if self.is_off() && !version.at_least(3, 0) {
+            format!("{:02x} ", self.0)
+        } else {
+            self.to_string()
+        }
+    }
+}
+
+/// Parses the names `Display` writes: `C-4`, `F#A`, `OFF`, or `---` for the
+/// empty note. Octaves are a hex digit, from 1.
+impl core::str::FromStr for Note {
+    type Err = String;
+
+    fn from_str(s: &str) -> core::result::Result<Self, String> {
+        match s {
+            "---" => return Ok(Note::default()),
+            "OFF" => return Ok(Note(Self::MAX + 1)),
+            _ => (),
+        }
+        const NAMES: [&str; 12] = [
+            "C-", "C#", "D-", "D#", "E-", "F-", "F#", "G-", "G#", "A-", "A#", "B-",
+        ];
+        let invalid = || format!("Invalid note {:?}", s);
+        let (name, octave) = (
+            s.get(..2).ok_or_else(invalid)?,
+            s.get(2..).ok_or_else(invalid)?,
+        );
+        let n = NAMES.iter().position(|&n| n == name).ok_or_else(invalid)?;
+        let octave = u8::from_str_radix(octave, 16)
+            .ok()
+            .filter(|&o| o >= 1 && octave.len() == 1)
+            .ok_or_else(invalid)?;
+        let value = (octave as usize - 1) * 12 + n;
+        if value > Self::MAX as usize {
+            return Err(invalid());
+        }
+        Ok(Note(value as u8))
+    }
+}
 
 impl fmt::Display for Note {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.0 == 255 {
             write!(f, "---")
         } else if self.0 >= 0x80 {
-            write!(f, "OFF") // This isn't really true for < V3
+            write!(f, "OFF")
         } else {
             let oct = (self.0 / 12) + 1;
             let n = match self.0 % 12 {
@@ -492,15 +1568,53 @@ impl fmt::Display for Note {
     }
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Default, Hash)]
 pub struct Table {
     pub number: u8,
     pub steps: [TableStep; 16],
     version: Version,
 }
 impl Table {
+    /// Empty every step, keeping the table's number.
+    pub fn clear(&mut self) {
+        self.steps = Default::default();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.iter().all(TableStep::is_empty)
+    }
+
+    pub fn step(&self, i: usize) -> Option<&TableStep> {
+        self.steps.get(i)
+    }
+
+    pub fn step_mut(&mut self, i: usize) -> Option<&mut TableStep> {
+        self.steps.get_mut(i)
+    }
+
+    /// The table screen as data rather than text.
+    pub fn rows(&self) -> [TableRow; 16] {
+        core::array::from_fn(|i| {
+            let step = &self.steps[i];
+            TableRow {
+                transpose: step.transpose_semitones(),
+                velocity: (step.velocity != 255).then_some(step.velocity),
+                fx: fx_cells(step.fx_iter(), self.version),
+            }
+        })
+    }
+
     pub fn print_screen(&self) -> String {
-        (0..16).fold("  N  V  FX1   FX2   FX3  \n".to_string(), |s, row| {
+        self.print_rows(16)
+    }
+
+    /// Like `print_screen`, but stops after the last non-empty step.
+    pub fn print_compact(&self) -> String {
+        self.print_rows(used_steps(&self.steps, TableStep::is_empty))
+    }
+
+    fn print_rows(&self, n: usize) -> String {
+        (0..n).fold("  N  V  FX1   FX2   FX3  \n".to_string(), |s, row| {
             s + &self.steps[row].print(row as u8, self.version) + "\n"
         })
     }
@@ -512,6 +1626,12 @@ impl Table {
             version,
         })
     }
+
+    fn write(&self, w: &mut Writer) {
+        for step in &self.steps {
+            step.write(w);
+        }
+    }
 }
 
 impl fmt::Display for Table {
@@ -525,7 +1645,7 @@ impl fmt::Debug for Table {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct TableStep {
     pub transpose: u8,
     pub velocity: u8,
@@ -533,15 +1653,65 @@ pub struct TableStep {
     pub fx2: FX,
     pub fx3: FX,
 }
+impl Default for TableStep {
+    fn default() -> Self {
+        Self {
+            transpose: 0,
+            velocity: 255,
+            fx1: FX::default(),
+            fx2: FX::default(),
+            fx3: FX::default(),
+        }
+    }
+}
 impl TableStep {
+    /// FX column `slot` (0-2), or `None` past the last.
+    pub fn fx(&self, slot: usize) -> Option<&FX> {
+        match slot {
+            0 => Some(&self.fx1),
+            1 => Some(&self.fx2),
+            2 => Some(&self.fx3),
+            _ => None,
+        }
+    }
+
+    pub fn fx_mut(&mut self, slot: usize) -> Option<&mut FX> {
+        match slot {
+            0 => Some(&mut self.fx1),
+            1 => Some(&mut self.fx2),
+            2 => Some(&mut self.fx3),
+            _ => None,
+        }
+    }
+
+    pub fn fx_iter(&self) -> impl Iterator<Item = &FX> {
+        [&self.fx1, &self.fx2, &self.fx3].into_iter()
+    }
+
+    pub fn fx_iter_mut(&mut self) -> impl Iterator<Item = &mut FX> {
+        [&mut self.fx1, &mut self.fx2, &mut self.fx3].into_iter()
+    }
+
+    /// The step's transpose, stored as a two's complement semitone count. There's
+    /// no empty value: `00` is both "no transpose" and "transpose by 0".
+    pub fn transpose_semitones(&self) -> i8 {
+        self.transpose as i8
+    }
+
+    pub fn set_transpose_semitones(&mut self, semitones: i8) {
+        self.transpose = semitones as u8;
+    }
+
+    /// Table transposes are signed and have no empty value, so a step is
+    /// empty when it does not transpose (`00`) and has no velocity or FX.
+    pub fn is_empty(&self) -> bool {
+        self.transpose == 0 && self.velocity == 255 && self.fx_iter().all(FX::is_empty)
+    }
+
     pub fn print(&self, row: u8, version: Version) -> String {
-        let transpose = if self.transpose == 255 {
-            format!("--")
-        } else {
-            format!("{:02x}", self.transpose)
-        };
+        let transpose = format!("{:02x}", self.transpose);
         let velocity = if self.velocity == 255 {
-            format!("--")
+            "--".to_string()
         } else {
             format!("{:02x}", self.velocity)
         };
@@ -565,25 +1735,61 @@ impl TableStep {
             fx3: FX::from_reader(reader)?,
         })
     }
+
+    fn write(&self, w: &mut Writer) {
+        w.write(self.transpose);
+        w.write(self.velocity);
+        self.fx_iter().for_each(|fx| fx.write(w));
+    }
 }
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Hash)]
 pub struct Groove {
     pub number: u8,
     pub steps: [u8; 16],
 }
+impl Default for Groove {
+    fn default() -> Self {
+        let mut steps = [255; 16];
+        steps[0] = 6;
+        steps[1] = 6;
+        Self { number: 0, steps }
+    }
+}
 impl Groove {
     fn from_reader(reader: &Reader, number: u8) -> Result<Self> {
         Ok(Self {
             number,
-            steps: reader.read_bytes(16).try_into().unwrap(),
+            steps: reader.read_array()?,
         })
     }
 
+    fn write(&self, w: &mut Writer) {
+        w.write_bytes(&self.steps);
+    }
+
+    /// The steps up to the first disabled one, or all 16 if none is disabled.
+    /// Disabling a step in the middle therefore shortens the groove to the
+    /// steps before it.
     pub fn active_steps(&self) -> &[u8] {
-        let end = (&self.steps).iter().position(|&x| x == 255).unwrap_or(15);
+        let end = self.steps.iter().position(|&x| x == 255).unwrap_or(16);
         &self.steps[0..end]
     }
+
+    /// Set step `i` (0-15) to `ticks`. `None` disables the step (`--`).
+    pub fn set_step(&mut self, i: usize, ticks: Option<u8>) -> core::result::Result<(), String> {
+        let step = self
+            .steps
+            .get_mut(i)
+            .ok_or_else(|| format!("Groove step {} is out of range", i))?;
+        *step = ticks.unwrap_or(255);
+        Ok(())
+    }
+
+    /// Reset to the default 6/6 groove.
+    pub fn clear(&mut self) {
+        self.steps = Self::default().steps;
+    }
 }
 
 impl fmt::Display for Groove {
@@ -600,13 +1806,19 @@ impl fmt::Debug for Groove {
 #[cfg(test)]
 mod tests {
     use crate::*;
-    use std::fs::File;
 
     fn test_file() -> Song {
-        let mut f = File::open("./examples/songs/TEST-FILE.m8s").expect("Could not open TEST-FILE");
-        Song::read(&mut f).expect("Could not parse TEST-FILE")
+        let buf =
+            std::fs::read("./examples/songs/TEST-FILE.m8s").expect("Could not open TEST-FILE");
+        Song::from_bytes(buf).expect("Could not parse TEST-FILE")
+    }
+
+    fn default_file() -> Song {
+        let buf = std::fs::read("./examples/songs/DEFAULT.m8s").expect("Could not open DEFAULT");
+        Song::from_bytes(buf).expect("Could not parse DEFAULT")
     }
 
+    #[allow(clippy::match_like_matches_macro, clippy::bool_assert_comparison)]
     #[test]
     fn test_instrument_reading() {
         let test_file = test_file();
@@ -796,6 +2008,2073 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_describe_mods() {
+        let test_file = test_file();
+        let version = test_file.version;
+        assert!(test_file.instruments[0].describe_mods(version).is_empty());
+        let lines = test_file.instruments[2].describe_mods(version);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("TRIG ENV → PAN, "));
+        assert!(lines[1].starts_with("TRACKING ENV → REDUX, "));
+        assert!(lines[2].starts_with("LFO → OFF, "));
+        assert!(lines[3].starts_with("LFO → OFF, "));
+        assert_eq!(
+            test_file.instruments[1].describe_mods(version)[0],
+            "AHD ENV → VOLUME, amt cf, att 10, hold 20, dec 30"
+        );
+
+        // Modulators only target each other from 3.0
+        let kind = InstrumentKind::WavSynth;
+        assert_eq!(kind.destination_names(version)[0x0B], "MOD AMT");
+        let v2 = Version::new(2, 7, 0);
+        assert_eq!(kind.destination_names(v2).len(), 0x0B);
+        let lfo = Mod::LFO(LFO {
+            shape: 0,
+            dest: 0x0B,
+            trigger_mode: 0,
+            freq: 0x10,
+            amount: 0xFF,
+        });
+        let names = kind.destination_names(v2);
+        assert!(lfo.describe(&names).starts_with("LFO → 0b, "));
+    }
+
+    #[test]
+    fn test_duplicate_chain() {
+        let mut song = test_file();
+        let shallow = song.duplicate_chain(0x10, false).unwrap();
+        assert_eq!(shallow, 0x00);
+        assert_eq!(song.chains[0].steps, song.chains[0x10].steps);
+        assert_eq!(song.chains[0].number, 0x00);
+
+        let deep = song.duplicate_chain(0x10, true).unwrap();
+        assert_eq!(deep, 0x01);
+        let new_phrase = song.chains[1].steps[0].phrase;
+        assert_ne!(new_phrase, 0x10);
+        assert!(song.chains[1].steps[0..4]
+            .iter()
+            .all(|s| s.phrase == new_phrase));
+        assert_eq!(
+            song.phrases[new_phrase as usize].steps,
+            song.phrases[0x10].steps
+        );
+
+        assert!(song.duplicate_chain(0xFF, false).is_err());
+    }
+
+    #[test]
+    fn test_transpose() {
+        assert_eq!(Note(0x10).transpose(-12), Note(0x04));
+        assert_eq!(Note(0x02).transpose(-12), Note(0x00));
+        assert_eq!(Note(0x7A).transpose(12), Note(0x7F));
+        assert_eq!(Note(0x80).transpose(12), Note(0x80));
+        assert_eq!(Note(0xFF).transpose(12), Note(0xFF));
+
+        let mut song = test_file();
+        let original = song.phrases[0x10].steps[0].note;
+        song.transpose_all(5);
+        assert_eq!(song.phrases[0x10].steps[0].note, original.transpose(5));
+        assert!(song.phrases[0x10].steps[1].note.is_off());
+        assert!(song.phrases[0x10].steps[2].note.is_empty());
+
+        let mut chain = song.chains[0x10].clone();
+        chain.transpose(-1);
+        assert_eq!(chain.steps[0].transpose, 0xFF);
+        assert_eq!(chain.steps[4].transpose, 0x00);
+        chain.transpose(127);
+        assert_eq!(chain.steps[0].transpose, 0x7E);
+        chain.transpose(10);
+        assert_eq!(chain.steps[0].transpose, 0x7F);
+    }
+
+    #[test]
+    fn test_velocities() {
+        let mut song = test_file();
+        song.scale_velocities(0.5);
+        assert_eq!(song.phrases[0x10].steps[0].velocity, 0x32);
+        assert_eq!(song.phrases[0x10].steps[1].velocity, 0xFF);
+        song.scale_velocities(100.0);
+        assert_eq!(song.phrases[0x10].steps[0].velocity, 0x7F);
+        song.set_all_velocities(0xF0);
+        assert_eq!(song.phrases[0x20].steps[0].velocity, 0x7F);
+        song.set_all_velocities(0x40);
+        assert_eq!(song.phrases[0x20].steps[0].velocity, 0x40);
+        assert_eq!(song.phrases[0x20].steps[1].velocity, 0xFF);
+    }
+
+    #[test]
+    fn test_step_setters() {
+        let song = test_file();
+        let mut step = song.phrases[0x10].steps[0].clone();
+        assert!(!step.is_empty());
+        step.set_note(None);
+        step.set_velocity(None);
+        step.set_instrument(None);
+        step.set_fx(0, None).unwrap();
+        step.set_fx(1, None).unwrap();
+        assert!(step.is_empty());
+        assert_eq!(step, Step::default());
+
+        step.set_fx(
+            2,
+            Some(FX {
+                command: 0x02,
+                value: 0x40,
+            }),
+        )
+        .unwrap();
+        assert_eq!(step.fx3.command, 0x02);
+        assert!(step.set_fx(FX_COLUMNS, None).is_err());
+        step.clear();
+        assert!(step.is_empty());
+    }
+
+    #[test]
+    fn test_empty_defaults() {
+        // Defaults are the device's empty values, not zero
+        let fx = FX::default();
+        assert_eq!(fx.command, 0xFF);
+        assert_eq!(fx.value, 0);
+        assert!(fx.is_empty());
+        assert_eq!(fx.print(Version::default()), "---00");
+
+        let step = Step::default();
+        assert_eq!(step.note.0, 0xFF);
+        assert_eq!(step.velocity, 0xFF);
+        assert_eq!(step.instrument, 0xFF);
+        assert!(step.fx_iter().all(|fx| *fx == FX::default()));
+        assert!(step.is_empty());
+        assert_eq!(
+            step.print(0, Version::default()),
+            "0 --- -- -- ---00 ---00 ---00"
+        );
+
+        let step = TableStep::default();
+        assert_eq!(step.transpose, 0);
+        assert_eq!(step.velocity, 0xFF);
+        assert!(step.fx_iter().all(FX::is_empty));
+    }
+
+    #[test]
+    fn test_table_step_empty() {
+        let song = test_file();
+        assert!(song.tables[0].is_empty());
+        assert_eq!(song.tables[0].steps[0], TableStep::default());
+        assert!(song.tables[0].steps[0]
+            .print(0, song.version)
+            .starts_with("0 00 --"));
+
+        let step = TableStep {
+            transpose: 0xFF,
+            ..Default::default()
+        };
+        assert!(!step.is_empty());
+        assert!(step.print(0, song.version).starts_with("0 ff --"));
+    }
+
+    #[test]
+    fn test_instrument_alignment() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let reader = Reader::new(buf);
+        let version = Version::from_reader(&reader).unwrap();
+        reader.set_pos(layout::INSTRUMENTS);
+        for i in 0..8 {
+            Instrument::from_reader3(&reader, i as u8, version).unwrap();
+            assert_eq!(
+                reader.pos(),
+                layout::INSTRUMENTS + (i + 1) * layout::INSTRUMENT_SIZE
+            );
+        }
+    }
+
+    #[test]
+    fn test_layout() {
+        let song = test_file();
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let reader = Reader::new(buf);
+        let version = Version::from_reader(&reader).unwrap();
+        assert_eq!(reader.pos(), layout::VERSION_SIZE);
+
+        reader.set_pos(layout::NAME);
+        assert_eq!(reader.read_string(layout::NAME_SIZE), song.name);
+        reader.set_pos(layout::MIXER_SETTINGS);
+        assert_eq!(
+            MixerSettings::from_reader(&reader).unwrap(),
+            song.mixer_settings
+        );
+        assert_eq!(reader.pos(), layout::GROOVES);
+        assert_eq!(Groove::from_reader(&reader, 0).unwrap(), song.grooves[0]);
+        reader.set_pos(layout::SONG);
+        assert_eq!(SongSteps::from_reader(&reader).unwrap(), song.song);
+        assert_eq!(reader.pos(), layout::PHRASES);
+        reader.set_pos(layout::PHRASES + 0x10 * layout::PHRASE_SIZE);
+        assert_eq!(
+            Phrase::from_reader(&reader, 0x10, version).unwrap(),
+            song.phrases[0x10]
+        );
+        reader.set_pos(layout::CHAINS + 0x10 * layout::CHAIN_SIZE);
+        assert_eq!(
+            Chain::from_reader(&reader, 0x10).unwrap(),
+            song.chains[0x10]
+        );
+        reader.set_pos(layout::TABLES + layout::TABLE_SIZE);
+        assert_eq!(
+            Table::from_reader(&reader, 1, version).unwrap(),
+            song.tables[1]
+        );
+        reader.set_pos(layout::EFFECTS_SETTINGS);
+        assert_eq!(
+            EffectsSettings::from_reader(&reader).unwrap(),
+            song.effects_settings
+        );
+        assert_eq!(
+            reader.pos(),
+            layout::EFFECTS_SETTINGS + layout::EFFECTS_SETTINGS_SIZE
+        );
+        reader.set_pos(layout::SCALES + layout::SCALE_SIZE);
+        assert_eq!(Scale::from_reader(&reader, 1).unwrap(), song.scales[1]);
+
+        let sampler = layout::INSTRUMENTS + 3 * layout::INSTRUMENT_SIZE;
+        reader.set_pos(sampler + layout::instrument::SAMPLE_PATH);
+        assert_eq!(
+            reader.read_string(layout::instrument::SAMPLE_PATH_SIZE),
+            "/Samples/Drums/Hits/TR505/bass drum 505.wav"
+        );
+    }
+
+    #[test]
+    fn test_writer_padding() {
+        let mut w = Writer::new(vec![0xAA; 4]);
+        w.seek(1);
+        w.write(1);
+        w.skip(1);
+        w.fill(2, 2);
+        assert_eq!(w.pos(), 5);
+        w.fill_till(3, 7);
+        w.fill_till(4, 6);
+        w.seek(9);
+        w.write(5);
+        w.pad_to(12);
+        assert_eq!(w.written_len(), 12);
+        assert_eq!(w.finish(), vec![0xAA, 1, 0xAA, 2, 2, 3, 3, 0, 0, 5, 0, 0]);
+    }
+
+    #[test]
+    fn test_writer_finish_checked() {
+        let mut w = Writer::new(vec![0; 8]);
+        w.fill(1, 4);
+        assert!(w.finish_checked(8).is_err());
+
+        let mut w = Writer::new(vec![0; 8]);
+        w.fill(1, 10);
+        assert!(w.finish_checked(8).is_err());
+
+        let mut w = Writer::new(vec![0; 8]);
+        w.seek(6);
+        w.fill(1, 2);
+        assert_eq!(w.finish_checked(8).unwrap(), vec![0, 0, 0, 0, 0, 0, 1, 1]);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_read_dir() {
+        let songs = read_dir("./examples/songs").unwrap();
+        assert_eq!(songs.len(), 2);
+        assert!(songs.iter().all(|(_, song)| song.is_ok()));
+
+        let dir = std::env::temp_dir().join("m8-files-test-read-dir");
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::copy("./examples/songs/TEST-FILE.m8s", dir.join("nested/A.m8s")).unwrap();
+        std::fs::write(dir.join("BROKEN.m8s"), b"M8VERSION").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"").unwrap();
+
+        let songs = read_dir(&dir).unwrap();
+        assert_eq!(songs.len(), 1);
+        assert!(matches!(songs[0].1, Err(M8Error::Parse(_))));
+        let songs = read_dir_recursive(&dir).unwrap();
+        assert_eq!(songs.len(), 2);
+        assert!(matches!(songs[0].1, Err(M8Error::Parse(_))));
+        assert_eq!(songs[1].1.as_ref().unwrap().name, "TEST-FILE");
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("missing"), dir.join("DANGLING.m8s")).unwrap();
+            let songs = read_dir(&dir).unwrap();
+            assert!(matches!(songs[1].1, Err(M8Error::Io(_))));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(read_dir(&dir), Err(M8Error::Io(_))));
+    }
+
+    #[test]
+    fn test_byte_size() {
+        for path in [
+            "./examples/songs/TEST-FILE.m8s",
+            "./examples/songs/DEFAULT.m8s",
+        ] {
+            let buf = std::fs::read(path).unwrap();
+            let song = Song::from_bytes(buf.clone()).unwrap();
+            assert_eq!(Song::byte_size(song.version), buf.len());
+        }
+        let v2_4 = Version::new(2, 4, 0);
+        assert_eq!(Song::byte_size(v2_4), 0x1A97E);
+
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        assert_eq!(
+            Song::from_bytes(buf[..0x1A97E].to_vec()),
+            Err(ParseError(
+                "File is too short for a 3.0.4 song: needs 0x1ad1e bytes, got 0x1a97e".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_new_empty() {
+        let default = default_file();
+        let mut empty = Song::new_empty(default.version);
+        empty.name = "DEFAULT".to_string();
+        assert_eq!(empty, default);
+        assert_eq!(default.scales[8].name, "MINOR PENTATONIC");
+    }
+
+    #[test]
+    fn test_template_bytes() {
+        let default = default_file();
+        let bytes = Song::template_bytes(default.version).unwrap();
+        assert_eq!(bytes.len(), Song::byte_size(default.version));
+        let mut song = Song::from_bytes(bytes).unwrap();
+        assert_eq!(song, Song::new_empty(default.version));
+        song.name = "DEFAULT".to_string();
+        assert_eq!(song, default);
+
+        let version = test_file().version;
+        let song = Song::from_bytes(Song::template_bytes(version).unwrap()).unwrap();
+        assert_eq!(song, Song::new_empty(version));
+        assert!(Song::template_bytes(Version::new(4, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn test_import_instrument() {
+        let src = test_file();
+        let mut song = test_file();
+        assert!(song.import_instrument(&src, 3, 2, false).is_err());
+        assert!(song.import_instrument(&src, 0, 20, false).is_err());
+        song.import_instrument(&src, 3, 20, false).unwrap();
+        assert_eq!(song.instruments[20].number(), Some(20));
+        let mut expected = src.instruments[3].clone();
+        expected.set_number(20);
+        assert_eq!(song.instruments[20], expected);
+        song.import_instrument(&src, 5, 20, true).unwrap();
+        assert!(matches!(song.instruments[20], Instrument::HyperSynth(_)));
+
+        let mut old = Song::new_empty(Version::new(2, 7, 0));
+        assert!(old.import_instrument(&src, 5, 0, false).is_err());
+        // 2.x can't store the wavsynth's ADSR and drum envelopes
+        assert!(old.import_instrument(&src, 1, 0, false).is_err());
+        old.import_instrument(&src, 4, 0, false).unwrap();
+
+        // Imports are saved
+        let mut w = Writer::new(std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap());
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.instruments[20], song.instruments[20]);
+    }
+
+    #[test]
+    fn test_set_version() {
+        let mut song = test_file();
+        assert!(song.set_version(Version::new(2, 7, 0)).is_err());
+        song.set_version(Version::new(3, 1, 0)).unwrap();
+        assert_eq!(song.phrases[0].version, song.version);
+
+        let v2_4 = Version::new(2, 4, 0);
+        let mut song = default_file();
+        song.set_version(v2_4).unwrap();
+        assert!(song.scales.iter().all(|s| s.name == "CHROMATIC"));
+        song.set_version(Version::new(2, 7, 0)).unwrap();
+        assert_eq!(song.scales, Scale::presets());
+        song.scales[3].name = "MINE".to_string();
+        assert!(song.set_version(v2_4).is_err());
+    }
+
+    #[test]
+    fn test_instrument_table() {
+        let mut song = test_file();
+        assert!(!song.instrument_has_table(1));
+        assert_eq!(song.instrument_table(1), &song.tables[1]);
+        song.instrument_table_mut(1).steps[0].velocity = 0x10;
+        assert_eq!(song.tables[1].steps[0].velocity, 0x10);
+        assert!(song.instrument_has_table(1));
+    }
+
+    #[test]
+    fn test_fx_value_display() {
+        let v3 = Version::default();
+        let fx = |s: &str, value| FX {
+            command: command_index(v3, s).unwrap(),
+            value,
+        };
+        assert_eq!(fx("TSP", 0xF4).value_display(v3), "-0c");
+        assert_eq!(fx("PSL", 0x10).value_display(v3), "+10");
+        assert_eq!(fx("VMV", 0xF4).value_display(v3), "f4");
+        assert_eq!(FX::default().value_display(v3), "00");
+    }
+
+    #[test]
+    fn test_instruments_of_kind() {
+        let song = test_file();
+        let samplers: Vec<usize> = song.samplers().map(|(ix, _)| ix).collect();
+        assert!(!samplers.is_empty());
+        assert_eq!(
+            samplers,
+            song.instruments_of_kind(InstrumentKind::Sampler)
+                .map(|(ix, _)| ix)
+                .collect::<Vec<usize>>()
+        );
+        assert!(song
+            .fm_synths()
+            .all(|(ix, _)| song.instruments[ix].kind() == Some(InstrumentKind::FMSynth)));
+        assert_eq!(Instrument::None.kind(), None);
+    }
+
+    #[test]
+    fn test_sample_paths() {
+        let mut song = test_file();
+        let paths = song.sample_paths();
+        assert!(!paths.is_empty());
+        assert!(paths.iter().all(|(_, p)| !p.is_empty()));
+        let (ix, path) = paths[0];
+        let (n, path) = (paths.len(), path.to_string());
+
+        assert!(song.instruments[0x70] == Instrument::None);
+        song.import_instrument(&test_file(), ix, 0x70, false)
+            .unwrap();
+        assert_eq!(song.sample_paths().len(), n + 1);
+        assert_eq!(
+            song.unique_sample_paths()
+                .iter()
+                .filter(|p| **p == path)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_remap_sample_paths() {
+        let mut song = test_file();
+        let before: Vec<(usize, String)> = song
+            .sample_paths()
+            .iter()
+            .map(|(ix, p)| (*ix, p.to_string()))
+            .collect();
+
+        assert!(song
+            .remap_sample_paths(|p| Some(format!("{}{}", "/x".repeat(64), p)))
+            .is_err());
+        assert_eq!(song.sample_paths().len(), before.len());
+        assert_eq!(song.sample_paths()[0].1, before[0].1);
+
+        song.remap_sample_paths(|p| Some(format!("/Other{}", p)))
+            .unwrap();
+        for ((ix, old), (new_ix, new)) in before.iter().zip(song.sample_paths()) {
+            assert_eq!(*ix, new_ix);
+            assert_eq!(format!("/Other{}", old), new);
+        }
+        song.remap_sample_paths(|_| None).unwrap();
+        assert!(song.sample_paths()[0].1.starts_with("/Other"));
+
+        // The new paths are saved
+        let mut w = Writer::new(std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap());
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.sample_paths(), song.sample_paths());
+    }
+
+    #[test]
+    fn test_fx_columns() {
+        let song = test_file();
+        let step = &song.phrases[0].steps[0];
+        assert_eq!(step.fx_iter().count(), FX_COLUMNS);
+        assert_eq!(step.fx(2), Some(&step.fx3));
+        assert_eq!(step.fx(FX_COLUMNS), None);
+
+        let mut step = TableStep::default();
+        step.fx_mut(1).unwrap().command = 0x02;
+        assert_eq!(step.fx2.command, 0x02);
+        assert_eq!(step.fx_iter().filter(|fx| !fx.is_empty()).count(), 1);
+        assert!(step.fx(FX_COLUMNS).is_none());
+        assert!(step.fx_mut(FX_COLUMNS).is_none());
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = test_file();
+        let mut b = test_file();
+        assert!(a.diff(&b).is_empty());
+
+        b.tempo = 140.0;
+        b.song.steps[8 * 3 + 2] = 0x10;
+        b.phrases[4].steps[0].set_velocity(Some(0x20));
+        b.instruments[0x70] = b.instruments[1].clone();
+        b.effects_settings.chorus_mod_depth ^= 1;
+        assert_eq!(
+            a.diff(&b).changes,
+            vec![
+                SongChange::Metadata,
+                SongChange::SongRow(3),
+                SongChange::Phrase(4),
+                SongChange::Instrument(0x70),
+                SongChange::EffectsSettings,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let song = test_file();
+        assert_eq!(song.content_hash(), test_file().content_hash());
+        assert_ne!(song.content_hash(), default_file().content_hash());
+
+        let mut edited = song.clone();
+        edited.tempo = 121.0;
+        assert_ne!(song.content_hash(), edited.content_hash());
+        edited.tempo = song.tempo;
+        assert_eq!(song.content_hash(), edited.content_hash());
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        assert_eq!(Song::from_bytes(buf).unwrap(), test_file());
+        assert!(Song::from_bytes(vec![0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_sections() {
+        fn round_trip<T: Section>(buf: &[u8], offset: usize, n: usize, version: Version) {
+            let reader = Reader::new(buf.to_vec());
+            let sections: Vec<T> = read_sections(&reader, offset, n, version).unwrap();
+            assert_eq!(reader.pos(), offset + n * T::SIZE);
+
+            let mut w = Writer::new(vec![]);
+            write_sections(&mut w, offset, &sections);
+            assert_eq!(&w.finish()[offset..], &buf[offset..offset + n * T::SIZE]);
+        }
+
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let version = test_file().version;
+        round_trip::<Groove>(&buf, layout::GROOVES, 32, version);
+        round_trip::<Phrase>(&buf, layout::PHRASES, 255, version);
+        round_trip::<Step>(&buf, layout::PHRASES, 16, version);
+        round_trip::<Chain>(&buf, layout::CHAINS, 255, version);
+        round_trip::<ChainStep>(&buf, layout::CHAINS, 16, version);
+        round_trip::<Table>(&buf, layout::TABLES, 256, version);
+        round_trip::<TableStep>(&buf, layout::TABLES, 16, version);
+    }
+
+    #[test]
+    fn test_write_grooves() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let mut song = Song::from_bytes(buf.clone()).unwrap();
+        let mut w = Writer::new(buf.clone());
+        song.write(&mut w).unwrap();
+        assert_eq!(w.finish(), buf);
+
+        song.grooves[0].steps[..4].copy_from_slice(&[4, 8, 4, 8]);
+        let mut w = Writer::new(buf);
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.grooves[0].active_steps(), &[4, 8, 4, 8]);
+        assert_eq!(reread, song);
+    }
+
+    #[test]
+    fn test_write_with() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = Song::from_bytes(buf.clone()).unwrap();
+        let opts = |major, minor| WriteOptions {
+            version: Version {
+                major,
+                minor,
+                patch: 0,
+            },
+        };
+
+        let mut w = Writer::new(buf.clone());
+        song.write_with(opts(3, 1), &mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.version, opts(3, 1).version);
+
+        let mut w = Writer::new(buf.clone());
+        assert!(song.write_with(opts(2, 7), &mut w).is_err());
+        assert!(song.write_with(opts(4, 0), &mut w).is_err());
+        let mut w = Writer::new(buf[..0x1000].to_vec());
+        assert!(song.write(&mut w).is_err());
+        let mut w = Writer::new(vec![]);
+        assert!(song.write(&mut w).is_ok());
+    }
+
+    #[test]
+    fn test_groove_editing() {
+        let mut groove = Groove::default();
+        assert_eq!(groove.active_steps(), &[6, 6]);
+        groove.set_step(2, Some(3)).unwrap();
+        groove.set_step(3, Some(9)).unwrap();
+        assert_eq!(groove.active_steps(), &[6, 6, 3, 9]);
+        assert!(groove.set_step(16, Some(1)).is_err());
+        groove.set_step(1, None).unwrap();
+        assert_eq!(groove.active_steps(), &[6]);
+        assert_eq!(groove.steps[2], 3);
+
+        groove.steps = [1; 16];
+        assert_eq!(groove.active_steps().len(), 16);
+        groove.clear();
+        assert_eq!(groove, Groove::default());
+    }
+
+    #[test]
+    fn test_key_and_transpose() {
+        let mut song = test_file();
+        assert_eq!(song.transpose_semitones(), 12);
+        assert_eq!(song.key_note().to_string(), "G#3");
+        song.set_transpose_semitones(-3);
+        assert_eq!(song.transpose, 0xFD);
+        song.set_key(Note(2));
+        assert_eq!(song.key, 2);
+    }
+
+    #[test]
+    fn test_mixer_writing() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let mut song = Song::from_bytes(buf.clone()).unwrap();
+        assert_eq!(song.mixer_settings.track_volume(7), Some(0xE0));
+        assert_eq!(song.mixer_settings.track_volume(8), None);
+        assert!(song.mixer_settings.set_track_volume(8, 0).is_err());
+        song.mixer_settings.set_track_volume(2, 0x40).unwrap();
+        song.mixer_settings.analog_input = AnalogInputSettings::DualMono((
+            InputMixerSettings {
+                volume: 1,
+                chorus: 2,
+                delay: 3,
+                reverb: 4,
+            },
+            InputMixerSettings {
+                volume: 5,
+                chorus: 6,
+                delay: 7,
+                reverb: 8,
+            },
+        ));
+
+        let mut w = Writer::new(buf);
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.mixer_settings, song.mixer_settings);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut song = test_file();
+        song.clear_phrase(0).unwrap();
+        assert!(song.phrases[0].is_empty());
+        assert_eq!(song.phrases[0].number, 0);
+        assert_eq!(
+            song.clear_phrase(Song::N_PHRASES),
+            Err("Phrase ff is out of range".to_string())
+        );
+        assert!(song.clear_instrument(Song::N_INSTRUMENTS).is_err());
+        assert!(song.clear_chain(Song::N_CHAINS).is_err());
+        assert!(song.clear_table(usize::MAX).is_err());
+        song.clear_instrument(1).unwrap();
+        assert_eq!(song.instruments[1], Instrument::None);
+        let mut w = Writer::new(std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap());
+        song.write(&mut w).unwrap();
+        assert_eq!(
+            Song::from_bytes(w.finish()).unwrap().instruments[1],
+            Instrument::None
+        );
+
+        song.clear_all_instruments();
+        song.clear_all_chains();
+        song.clear_all_phrases();
+        song.clear_all_tables();
+        song.song = SongSteps::default();
+        let mut empty = Song::new_empty(song.version);
+        empty.directory = song.directory.clone();
+        empty.name = song.name.clone();
+        assert!(song.diff(&empty).changes.iter().all(|c| !matches!(
+            c,
+            SongChange::Phrase(_)
+                | SongChange::Chain(_)
+                | SongChange::Instrument(_)
+                | SongChange::Table(_)
+        )));
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        buf[11] = 0x04; // Major version 4
+        assert!(Song::from_bytes(buf.clone()).is_err());
+        let (song, warnings) = Song::from_bytes_lenient(buf).unwrap();
+        assert_eq!(song.version.major, 4);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(song.instruments, test_file().instruments);
+    }
+
+    #[test]
+    fn test_lenient_instruments() {
+        let mut buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        buf[layout::INSTRUMENTS + 2 * layout::INSTRUMENT_SIZE] = 0x0E; // Unknown kind
+        assert!(Song::from_bytes(buf.clone()).is_err());
+
+        let (song, warnings) = Song::from_bytes_lenient(buf).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Instrument 02"));
+        let mut expected = test_file();
+        expected.instruments[2] = Instrument::None;
+        assert_eq!(song, expected);
+    }
+
+    #[test]
+    fn test_command_registry() {
+        let v2 = Version::new(2, 7, 0);
+        let v3 = Version::default();
+        assert_eq!(sequencer_commands(v3)[0x19], "TSP");
+        assert_eq!(mixer_commands(v3)[0], "VMV");
+        assert_eq!(command_index(v3, "VMV"), Some(0x1B));
+        assert_eq!(command_index(v2, "VMV"), Some(0x17));
+        assert_eq!(command_index(v3, "IA7"), Some(0xA7));
+        assert_eq!(command_index(v2, "IA7"), None);
+        assert_eq!(command_index(v2, "OFF"), None);
+        for (i, c) in fx_command_table(v3).iter().enumerate() {
+            let fx = FX {
+                command: i as u8,
+                value: 0,
+            };
+            assert_eq!(fx.command_name(v3), Some(*c));
+        }
+    }
+
+    #[test]
+    fn test_instrument_command_table() {
+        let song = test_file();
+        let version = song.version;
+        let table = song.instruments[1].command_table(version);
+        assert_eq!(table[0], (0x80, "VOL"));
+        assert_eq!(table[3], (0x83, "OSC"));
+        // The modulators: AHD, ADSR, drum, then an LFO with only 4 commands
+        assert_eq!(table[0x12], (0x92, "EA1"));
+        assert_eq!(table[0x12 + 8], (0x9A, "SU2"));
+        assert_eq!(table.last(), Some(&(0xA4, "LF4")));
+        assert!(table.iter().all(|(c, _)| *c <= 0xA7));
+        assert!(song.instruments[0].command_table(version).is_empty());
+
+        let fm = song.instruments[4].command_table(version);
+        assert!(fm.contains(&(0x83, "ALG")));
+        let midi = song.instruments[6].command_table(version);
+        assert!(midi.contains(&(0x84, "CCA")));
+        assert!(midi.contains(&(0x8E, "EA1")));
+
+        // 2.x has fewer instrument commands
+        let v2 = Version::new(2, 7, 0);
+        let old = song.instruments[4].command_table(v2);
+        assert!(old.iter().all(|(c, _)| *c <= 0xA2));
+        assert!(old.len() < fm.len());
+    }
+
+    #[test]
+    fn test_reader_positioning() {
+        let reader = Reader::new(vec![1, 2, 3, 4]);
+        assert!(reader.try_set_pos(5).is_err());
+        reader.try_set_pos(4).unwrap();
+        assert!(reader.advance(1).is_err());
+        reader.rewind(3).unwrap();
+        assert_eq!(reader.read(), 2);
+        assert!(reader.rewind(3).is_err());
+        assert_eq!(reader.pos(), 2);
+        reader.advance(2).unwrap();
+        assert_eq!(reader.pos(), reader.len());
+    }
+
+    #[test]
+    fn test_reader_arrays() {
+        let reader = Reader::new(vec![1, 2, 3, 4, 5]);
+        let bytes = reader.read_vec(2);
+        assert!(reader.read_array::<4>().is_err());
+        assert_eq!(reader.read_array::<3>(), Ok([3, 4, 5]));
+        assert_eq!(bytes, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_instrument_from_slot_bytes() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        let start = layout::INSTRUMENTS + 3 * layout::INSTRUMENT_SIZE;
+        let slot = &buf[start..start + layout::INSTRUMENT_SIZE];
+        assert_eq!(
+            Instrument::from_slot_bytes(slot, 3, song.version).unwrap(),
+            song.instruments[3]
+        );
+        assert!(Instrument::from_slot_bytes(&slot[..100], 3, song.version).is_err());
+    }
+
+    #[test]
+    fn test_analysis() {
+        let mut song = test_file();
+        assert!(song.analysis().is_empty());
+
+        song.phrases[200] = song.phrases[16].clone();
+        song.phrases[200].number = 200;
+        song.instruments[100] = song.instruments[1].clone();
+        song.instruments[100].set_number(100);
+        let analysis = song.analysis();
+        assert!(analysis.findings.iter().any(|f| {
+            f.category == FindingCategory::DuplicatePhrases
+                && f.indices.first() == Some(&16)
+                && f.indices.contains(&200)
+        }));
+        assert!(analysis.findings.contains(&Finding {
+            category: FindingCategory::UnusedPhrase,
+            indices: vec![200],
+        }));
+        assert!(analysis
+            .of_category(FindingCategory::DuplicateInstruments)
+            .any(|f| f.indices.contains(&1) && f.indices.contains(&100)));
+        assert!(analysis.findings.contains(&Finding {
+            category: FindingCategory::UnusedInstrument,
+            indices: vec![100],
+        }));
+    }
+
+    #[test]
+    fn test_fm_editing() {
+        let mut song = test_file();
+        let fm = match &mut song.instruments[4] {
+            Instrument::FMSynth(fm) => fm,
+            i => panic!("Expected an FM synth, got {:?}", i.kind()),
+        };
+        assert!(fm.set_algo(FMSynth::N_ALGOS).is_err());
+        fm.set_algo(0x0B).unwrap();
+        assert_eq!(fm.algo, 0x0B);
+
+        let mut op = fm.operator(0).unwrap().clone();
+        op.set_ratio(2, 50);
+        assert!(fm.set_operator(4, op.clone()).is_err());
+        fm.set_operator(3, op).unwrap();
+        assert_eq!(fm.operators[3].ratio(), (2, 50));
+    }
+
+    #[test]
+    fn test_table_transpose() {
+        let mut table = Table::default();
+        assert!(table.step(16).is_none());
+        let step = table.step_mut(1).unwrap();
+        assert_eq!(step.transpose_semitones(), 0);
+        step.set_transpose_semitones(-12);
+        assert_eq!(step.transpose, 0xF4);
+        assert_eq!(table.step(1).unwrap().transpose_semitones(), -12);
+        assert!(!table.is_empty());
+    }
+
+    #[test]
+    fn test_tracks() {
+        let song = test_file();
+        assert!(song.track(8).is_none());
+        assert_eq!(song.tracks().count(), 8);
+
+        let track = song.track(6).unwrap();
+        assert_eq!(track.chain_numbers().collect::<Vec<_>>(), vec![(0, 112)]);
+        let phrases: Vec<_> = track.phrases().map(|(row, _, p)| (row, p.number)).collect();
+        assert_eq!(phrases, vec![(0, 128); 4]);
+        assert_eq!(song.track(7).unwrap().chains().count(), 0);
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        let version = test_file().version;
+        assert_eq!(version, Version::new(3, 0, 4));
+        assert!(version > Version::V3_0);
+        assert!(Version::new(2, 7, 0) < Version::V3_0);
+        assert!(Version::new(2, 10, 0) > Version::new(2, 5, 9));
+        assert_eq!(
+            version >= Version::V2_5,
+            version.at_least(Version::V2_5.major, Version::V2_5.minor)
+        );
+    }
+
+    #[test]
+    fn test_version_write() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        for version in [
+            test_file().version,
+            Version::new(2, 7, 0),
+            Version::new(3, 15, 9),
+        ] {
+            let mut w = Writer::new(vec![]);
+            version.write(&mut w);
+            let bytes = w.finish();
+            assert_eq!(bytes.len(), Version::SIZE);
+            assert_eq!(&bytes[..10], b"M8VERSION\0");
+            assert_eq!(Version::from_reader(&Reader::new(bytes)), Ok(version));
+        }
+        let mut w = Writer::new(vec![]);
+        test_file().version.write(&mut w);
+        assert_eq!(w.finish(), &buf[..Version::SIZE]);
+    }
+
+    #[test]
+    fn test_round_trip_examples() {
+        let mut paths: Vec<_> = std::fs::read_dir("./examples/songs")
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "m8s"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+
+        for path in paths {
+            let buf = std::fs::read(&path).unwrap();
+            let song = Song::from_bytes(buf.clone()).unwrap();
+
+            let mut w = Writer::new(buf.clone());
+            song.write(&mut w).unwrap();
+            let written = w.finish_checked(Song::byte_size(song.version)).unwrap();
+            assert!(written == buf, "{} changed on rewrite", path.display());
+
+            let mut w = Writer::new(Song::template_bytes(song.version).unwrap());
+            song.write(&mut w).unwrap();
+            let reread = Song::from_bytes(w.finish()).unwrap();
+            assert_eq!(reread, song, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_fx_labels() {
+        let version = test_file().version;
+        let mut step = Step::default();
+        assert!(!step.fx_is_instrument_command(0));
+        assert_eq!(step.fx_label(0, version).unwrap(), "---");
+        assert_eq!(step.fx_label(3, version), None);
+        assert!(!step.fx_is_instrument_command(3));
+
+        step.set_fx(
+            1,
+            Some(FX {
+                command: INSTRUMENT_COMMAND_START,
+                value: 0,
+            }),
+        )
+        .unwrap();
+        assert!(step.fx_is_instrument_command(1));
+        assert_eq!(
+            step.fx_label(1, version).unwrap(),
+            instrument_commands(version)[0]
+        );
+        step.set_fx(
+            2,
+            Some(FX {
+                command: command_index(version, "ARP").unwrap(),
+                value: 0,
+            }),
+        )
+        .unwrap();
+        assert!(!step.fx_is_instrument_command(2));
+        assert_eq!(step.fx_label(2, version).unwrap(), "ARP");
+    }
+
+    #[test]
+    fn test_rows() {
+        let song = test_file();
+        let phrase = &song.phrases[16];
+        let rows = phrase.rows();
+        for (row, step) in rows.iter().zip(phrase.steps.iter()) {
+            assert_eq!(row.note.is_none(), step.note.is_empty());
+            assert_eq!(row.instrument, Some(step.instrument).filter(|&i| i != 255));
+            for (cell, fx) in row.fx.iter().zip(step.fx_iter()) {
+                assert_eq!(
+                    cell.clone(),
+                    Some((fx.label(song.version), fx.value)).filter(|_| !fx.is_empty())
+                );
+            }
+        }
+        assert!(rows.iter().any(|r| r.note.is_some()));
+
+        let mut table = song.tables[0].clone();
+        assert!(table.rows().iter().all(|r| *r == TableRow::default()));
+        table.steps[3].set_transpose_semitones(-1);
+        assert_eq!(table.rows()[3].transpose, -1);
+    }
+
+    #[test]
+    fn test_validate_shape() {
+        let mut song = test_file();
+        assert!(song.validate_shape().is_ok());
+        song.phrases.truncate(3);
+        assert_eq!(
+            song.validate_shape(),
+            Err("Song has 3 phrases, expected 255".to_string())
+        );
+        assert!(song.write(&mut Writer::new(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_insert_remove_steps() {
+        let mut chain = Chain::default();
+        let step = |phrase| ChainStep {
+            phrase,
+            transpose: 0,
+        };
+        chain.insert_step(0, step(1)).unwrap();
+        chain.insert_step(0, step(2)).unwrap();
+        chain.insert_step(1, step(3)).unwrap();
+        let phrases: Vec<_> = chain.steps[..4].iter().map(|s| s.phrase).collect();
+        assert_eq!(phrases, vec![2, 3, 1, 255]);
+        assert!(chain.insert_step(16, step(4)).is_err());
+
+        assert_eq!(chain.remove_step(0), Some(step(2)));
+        assert_eq!(chain.steps[0].phrase, 3);
+        assert!(chain.steps[15].is_empty());
+        assert_eq!(chain.remove_step(16), None);
+
+        chain.steps[15] = step(5);
+        assert!(chain.insert_step(0, step(6)).is_err());
+        assert_eq!(chain.steps[0].phrase, 3);
+
+        let mut phrase = Phrase::default();
+        let mut step = Step::default();
+        step.set_note(Some(Note(36)));
+        phrase.insert_step(2, step.clone()).unwrap();
+        phrase.insert_step(0, Step::default()).unwrap();
+        assert_eq!(phrase.steps[3], step);
+        assert_eq!(phrase.remove_step(3), Some(step));
+        assert!(phrase.is_empty());
+    }
+
+    #[test]
+    fn test_m8_file() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        assert_eq!(
+            M8File::from_bytes(buf.clone()).unwrap(),
+            M8File::Song(Box::new(song.clone()))
+        );
+
+        let header = &buf[..Version::SIZE];
+        let slot = layout::INSTRUMENTS + layout::INSTRUMENT_SIZE;
+        let instrument = [header, &buf[slot..slot + layout::INSTRUMENT_SIZE]].concat();
+        assert_eq!(
+            M8File::from_bytes(instrument).unwrap(),
+            M8File::Instrument(Instrument::from_slot_bytes(&buf[slot..], 0, song.version).unwrap())
+        );
+        let scale = [
+            header,
+            &buf[layout::SCALES..layout::SCALES + layout::SCALE_SIZE],
+        ]
+        .concat();
+        assert!(matches!(M8File::from_bytes(scale), Ok(M8File::Scale(s)) if s.name == "CHROMATIC"));
+        let theme = [header, &[0x10; 39]].concat();
+        assert!(matches!(M8File::from_bytes(theme), Ok(M8File::Theme(t)) if t.cursor.g == 0x10));
+
+        assert!(M8File::from_bytes(header.to_vec()).is_err());
+        assert!(M8File::from_bytes(vec![0; 0x200]).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let song = test_file();
+        assert_eq!(song.phrases.len(), limits::N_PHRASES);
+        assert_eq!(song.instruments.len(), limits::N_INSTRUMENTS);
+        assert_eq!(song.scales.len(), limits::N_SCALES);
+        assert_eq!(
+            song.song.steps.len(),
+            limits::N_TRACKS * limits::N_SONG_ROWS
+        );
+        assert_eq!(song.chains[0].steps.len(), limits::N_STEPS);
+        assert_eq!(
+            song.instruments[1].mods().unwrap().len(),
+            limits::N_MODULATORS
+        );
+    }
+
+    #[test]
+    fn test_print_compact() {
+        let mut chain = Chain::default();
+        assert_eq!(chain.print_compact(), "  PH TSP\n");
+        chain.steps[2].phrase = 0x10;
+        assert_eq!(
+            chain.print_compact(),
+            "  PH TSP\n0 -- 00\n1 -- 00\n2 10 00\n"
+        );
+        assert!(chain.print_screen().starts_with(&chain.print_compact()));
+
+        let song = test_file();
+        let phrase = &song.phrases[16];
+        let rows = phrase.print_compact().lines().count() - 1;
+        assert!(rows <= 16 && !phrase.steps[rows - 1].is_empty());
+        assert_eq!(song.tables[0].print_compact().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_move_slots() {
+        let mut song = test_file();
+        let instrument = song.instruments[1].clone();
+        song.tables[1].steps[0].set_transpose_semitones(3);
+        song.phrases[16].steps[0].fx1 = FX {
+            command: command_index(song.version, "TBL").unwrap(),
+            value: 1,
+        };
+        let plays_1 = song.phrases[16]
+            .steps
+            .iter()
+            .filter(|s| s.instrument == 1)
+            .count();
+        assert!(plays_1 > 0);
+        assert!(song.move_instrument(1, 2).is_err());
+        assert!(song.move_instrument(1, 128).is_err());
+
+        song.move_instrument(1, 40).unwrap();
+        assert_eq!(song.instruments[1], Instrument::None);
+        let mut expected = instrument;
+        expected.set_number(40);
+        assert_eq!(song.instruments[40], expected);
+        assert!(song.tables[1].is_empty());
+        assert_eq!(song.tables[40].steps[0].transpose_semitones(), 3);
+        assert_eq!(song.tables[40].number, 40);
+        let plays_40 = song.phrases[16]
+            .steps
+            .iter()
+            .filter(|s| s.instrument == 40)
+            .count();
+        assert_eq!(plays_40, plays_1);
+        assert_eq!(song.phrases[16].steps[0].fx1.value, 40);
+
+        let chain = song.chains[16].clone();
+        assert!(song.move_chain(16, 32).is_err());
+        song.move_chain(16, 200).unwrap();
+        assert_eq!(song.song.steps[0], 200);
+        assert_eq!(song.chains[200].steps, chain.steps);
+        assert!(song.chains[16].is_empty());
+
+        song.move_phrase(16, 250).unwrap();
+        let phrases: Vec<_> = song.chains[200].steps.iter().map(|s| s.phrase).collect();
+        assert_eq!(phrases[..5], [250, 250, 250, 250, 255]);
+        assert!(song.phrases[16].is_empty());
+        assert_eq!(song.phrases[250].number, 250);
+
+        song.move_table(40, 255).unwrap();
+        assert_eq!(song.phrases[250].steps[0].fx1.value, 255);
+        assert!(song.tables[40].is_empty());
+    }
+
+    #[test]
+    fn test_swap_slots() {
+        // What each track plays, with slot numbers resolved away
+        fn render(song: &Song) -> Vec<String> {
+            song.tracks()
+                .flat_map(|track| track.phrases().collect::<Vec<_>>())
+                .flat_map(|(row, chain_step, phrase)| {
+                    phrase.steps.iter().map(move |step| {
+                        let mut instrument = song
+                            .instruments
+                            .get(step.instrument as usize)
+                            .cloned()
+                            .unwrap_or_default();
+                        instrument.set_number(0);
+                        let table = song
+                            .tables
+                            .get(step.instrument as usize)
+                            .map(|t| t.print_screen());
+                        let fx: Vec<_> = step.fx_iter().map(|fx| fx.print(song.version)).collect();
+                        format!(
+                            "{} {} {} {} {:?} {:?} {:?}",
+                            row,
+                            chain_step.transpose,
+                            step.note,
+                            step.velocity,
+                            fx,
+                            instrument,
+                            table
+                        )
+                    })
+                })
+                .collect()
+        }
+
+        let mut song = test_file();
+        song.tables[2].steps[0].set_transpose_semitones(7);
+        let before = render(&song);
+        let (one, two) = (song.instruments[1].clone(), song.instruments[2].clone());
+
+        song.swap_instruments(1, 2).unwrap();
+        assert_eq!(song.instruments[1].kind(), two.kind());
+        assert_eq!(song.instruments[2].kind(), one.kind());
+        assert_eq!(song.instruments[2].number(), Some(2));
+        assert_eq!(song.tables[1].steps[0].transpose_semitones(), 7);
+        song.swap_chains(16, 32).unwrap();
+        assert_eq!(song.song.steps[..2], [32, 16]);
+        song.swap_phrases(16, 48).unwrap();
+        song.swap_phrases(128, 200).unwrap();
+        assert!(song.phrases[128].is_empty());
+        assert_eq!(render(&song), before);
+
+        assert!(song.swap_chains(0, 255).is_err());
+        song.swap_instruments(1, 1).unwrap();
+        assert_eq!(render(&song), before);
+
+        // The swap survives a save
+        let mut w = Writer::new(std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap());
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.instruments[1].kind(), two.kind());
+        assert_eq!(reread.instruments[2].kind(), one.kind());
+        assert_eq!(render(&reread), before);
+        assert_eq!(reread, song);
+    }
+
+    #[test]
+    fn test_read_with_version() {
+        let mut buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        // A header claiming 9.0.0
+        buf[10] = 0;
+        buf[11] = 9;
+        assert!(Song::from_bytes(buf.clone()).is_err());
+        assert_eq!(
+            Song::from_bytes_with_version(buf.clone(), song.version).unwrap(),
+            song
+        );
+        assert!(Song::from_bytes_with_version(buf.clone(), Version::new(9, 0, 0)).is_err());
+        let short = buf[..Song::byte_size(song.version) - 1].to_vec();
+        assert!(Song::from_bytes_with_version(short, song.version).is_err());
+
+        let slot = layout::INSTRUMENTS + layout::INSTRUMENT_SIZE;
+        let instrument = [
+            &buf[..Version::SIZE],
+            &buf[slot..slot + layout::INSTRUMENT_SIZE],
+        ]
+        .concat();
+        let mut expected = song.instruments[1].clone();
+        expected.set_number(0);
+        assert_eq!(
+            Instrument::from_bytes_with_version(instrument, song.version).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_offsets() {
+        let mut buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        let v = song.version;
+
+        let step = Song::phrase_offset(v, 0x10).unwrap() + Phrase::step_offset(0).unwrap();
+        assert_eq!(buf[step], song.phrases[0x10].steps[0].note.0);
+        assert_eq!(buf[step + 2], song.phrases[0x10].steps[0].instrument);
+        let fx = Song::table_offset(v, 3).unwrap()
+            + Table::step_offset(15).unwrap()
+            + TableStep::fx_offset(2).unwrap();
+        assert_eq!(fx + layout::FX_SIZE, Song::table_offset(v, 4).unwrap());
+        assert_eq!(
+            Song::chain_offset(v, 0x10).unwrap() + Chain::step_offset(1).unwrap(),
+            layout::CHAINS + 0x10 * layout::CHAIN_SIZE + 2
+        );
+        assert_eq!(buf[Song::song_step_offset(v, 0, 1).unwrap()], 0x20);
+        assert_eq!(
+            Song::instrument_offset(v, 1).unwrap(),
+            layout::INSTRUMENTS + layout::INSTRUMENT_SIZE
+        );
+
+        // Patch phrase 10, step 3, FX 2 in place
+        let fx = Song::phrase_offset(v, 0x10).unwrap()
+            + Phrase::step_offset(3).unwrap()
+            + Step::fx_offset(1).unwrap();
+        buf[fx + 1] = 0x42;
+        let patched = Song::from_bytes(buf).unwrap();
+        assert_eq!(patched.phrases[0x10].steps[3].fx2.value, 0x42);
+        assert_eq!(
+            patched.phrases[0x10].steps[3].fx2.command,
+            song.phrases[0x10].steps[3].fx2.command
+        );
+
+        assert_eq!(Song::phrase_offset(v, Song::N_PHRASES), None);
+        assert_eq!(Song::song_step_offset(v, 0, 8), None);
+        assert_eq!(Song::song_step_offset(v, 256, 0), None);
+        assert_eq!(Song::song_step_offset(v, usize::MAX, 0), None);
+        assert_eq!(Song::phrase_offset(v, usize::MAX), None);
+        assert_eq!(Phrase::step_offset(usize::MAX), None);
+        assert_eq!(Chain::step_offset(usize::MAX), None);
+        assert_eq!(Table::step_offset(usize::MAX), None);
+        assert_eq!(Step::fx_offset(usize::MAX), None);
+        assert_eq!(TableStep::fx_offset(usize::MAX), None);
+        assert_eq!(Song::song_step_offset(v, 255, 7), Some(layout::PHRASES - 1));
+        assert_eq!(Phrase::step_offset(16), None);
+        assert_eq!(Step::fx_offset(3), None);
+        assert_eq!(Song::phrase_offset(Version::new(4, 0, 0), 0), None);
+        assert_eq!(Song::scale_offset(Version::new(2, 4, 0), 0), None);
+        assert_eq!(Song::scale_offset(v, 0), Some(layout::SCALES));
+    }
+
+    #[test]
+    fn test_fx_new() {
+        let v3 = Version::V3_0;
+        let v2 = Version::new(2, 7, 0);
+        let del = FX::new(v3, "DEL", 0x40).unwrap();
+        assert_eq!(
+            del,
+            FX {
+                command: 2,
+                value: 0x40
+            }
+        );
+        assert_eq!(del.print(v3), "DEL40");
+        assert_eq!(FX::new(v3, "TBX", 1).unwrap().command, 0x17);
+        assert_eq!(FX::new(v2, "TBX", 1), None);
+        assert_eq!(FX::new(v3, "RND", 0).unwrap().print(v3), "RND00");
+        assert_eq!(FX::new(v2, "RAN", 0).unwrap().print(v2), "RAN00");
+
+        let ins = FX::new(v3, "IA7", 0x10).unwrap();
+        assert_eq!(ins.command, INSTRUMENT_COMMAND_START + 39);
+        assert!(ins.is_instrument_command());
+        assert_eq!(ins.print(v3), "IA710");
+        assert_eq!(FX::new(v2, "IA7", 0x10), None);
+        assert_eq!(FX::new(v3, "del", 0), None);
+        assert_eq!(FX::new(v3, "---", 0), None);
+    }
+
+    #[test]
+    fn test_note_display() {
+        let v2 = Version::new(2, 7, 0);
+        assert_eq!(Note(0x80).to_string(), "OFF");
+        assert_eq!(Note(0x80).display(Version::V3_0), "OFF");
+        assert_eq!(Note(0x80).display(v2), "80 ");
+        assert_eq!(Note(0x24).display(v2), "C-4");
+        assert_eq!(Note(255).display(v2), "---");
+
+        let mut step = Step::default();
+        step.set_note(Some(Note(0x81)));
+        assert_eq!(step.print(0, v2), "0 81  -- -- ---00 ---00 ---00");
+        assert_eq!(
+            step.print(0, Version::V3_0),
+            "0 OFF -- -- ---00 ---00 ---00"
+        );
+    }
+
+    #[test]
+    fn test_tempo_bytes() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let tempo = &buf[layout::TEMPO..layout::TEMPO + 4];
+        let mut song = test_file();
+        assert_eq!(song.tempo, 120.0);
+
+        let mut w = Writer::new(vec![]);
+        song.write(&mut w).unwrap();
+        let out = w.finish();
+        assert_eq!(&out[layout::TEMPO..layout::TEMPO + 4], tempo);
+
+        // Tempos that aren't whole BPM, and the smallest fraction a f32 holds
+        // there, survive exactly
+        for t in [133.33, 120.0 + f32::EPSILON * 64.0] {
+            song.tempo = t;
+            let mut w = Writer::new(buf.clone());
+            song.write(&mut w).unwrap();
+            let out = w.finish();
+            assert_eq!(&out[layout::TEMPO..layout::TEMPO + 4], &t.to_le_bytes());
+            assert_eq!(Song::from_bytes(out).unwrap().tempo.to_bits(), t.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_write_coverage() {
+        let coverage = test_file().write_coverage();
+        assert!(coverage.is_lossless());
+        assert!(coverage.writes(SongSection::MixerSettings));
+        assert!(coverage.writes(SongSection::Scales));
+        assert!(coverage.writes(SongSection::Instruments));
+
+        let old = Song::new_empty(Version::new(2, 4, 0)).write_coverage();
+        assert!(!old.writes(SongSection::Scales));
+        assert!(!old.dropped.contains(&SongSection::Scales));
+
+        let mut song = test_file();
+        song.instruments[1].clear();
+        let mut w = Writer::new(std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap());
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.instruments[1], Instrument::None);
+    }
+
+    #[test]
+    fn test_referenced_slot() {
+        let v3 = Version::V3_0;
+        let v2 = Version::new(2, 7, 0);
+        let fx = |v, m, value| FX::new(v, m, value).unwrap();
+        assert_eq!(
+            fx(v3, "TBX", 0x12).referenced_slot(v3),
+            Some((RefKind::Table, 0x12))
+        );
+        assert_eq!(
+            fx(v2, "TBL", 0x03).referenced_slot(v2),
+            Some((RefKind::Table, 0x03))
+        );
+        assert_eq!(
+            fx(v3, "SCG", 0x02).referenced_slot(v3),
+            Some((RefKind::Scale, 0x02))
+        );
+        assert_eq!(
+            fx(v3, "GRV", 0x1F).referenced_slot(v3),
+            Some((RefKind::Groove, 0x1F))
+        );
+        assert_eq!(fx(v3, "DEL", 0x40).referenced_slot(v3), None);
+        assert_eq!(fx(v3, "I00", 0x40).referenced_slot(v3), None);
+        assert_eq!(FX::default().referenced_slot(v3), None);
+
+        let (kind, value) = fx(v3, "SCA", 0x10).referenced_slot(v3).unwrap();
+        assert!(value as usize >= kind.count());
+        assert_eq!(RefKind::Table.count(), Song::N_TABLES);
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = test_file().stats();
+        assert_eq!(stats.used_instruments, 7);
+        assert_eq!(stats.used_chains, 7);
+        assert_eq!(stats.used_phrases, 7);
+        assert_eq!(stats.used_tables, 0);
+        assert_eq!(stats.instrument_kind_counts.len(), 7);
+        assert_eq!(
+            stats.instrument_kind_counts[0],
+            (InstrumentKind::WavSynth, 1)
+        );
+        assert!(stats.to_string().starts_with(
+            "7 instruments, 7 chains, 7 phrases, 0 tables, 1 scales, 1 grooves (1 WavSynth, "
+        ));
+
+        let empty = default_file().stats();
+        assert_eq!(empty, SongStats::default());
+        assert_eq!(
+            empty.to_string(),
+            "0 instruments, 0 chains, 0 phrases, 0 tables, 0 scales, 0 grooves"
+        );
+
+        let mut song = default_file();
+        song.phrases[0].steps[0].fx1 = FX::new(song.version, "GRV", 3).unwrap();
+        song.phrases[0].steps[1].fx1 = FX::new(song.version, "GRV", 3).unwrap();
+        song.phrases[0].steps[2].fx1 = FX::new(song.version, "GRV", 0xFE).unwrap();
+        let stats = song.stats();
+        assert_eq!(stats.used_grooves, 1);
+        assert_eq!(stats.used_phrases, 1);
+    }
+
+    #[test]
+    fn test_midi_out_write() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        let slot = layout::INSTRUMENTS + 6 * layout::INSTRUMENT_SIZE;
+        let Instrument::MIDIOut(midi) = &song.instruments[6] else {
+            panic!("Instrument 6 should be MIDI out");
+        };
+
+        let mut w = Writer::new(buf.clone());
+        w.seek(slot);
+        midi.write(&mut w, song.version);
+        assert_eq!(w.pos(), slot + layout::INSTRUMENT_SIZE);
+        assert_eq!(w.finish(), buf);
+
+        let mut edited = midi.clone();
+        edited.name = "OUT".to_string();
+        edited.transpose = !edited.transpose;
+        edited.table_tick = 3;
+        edited.port = 2;
+        edited.channel = 0x0F;
+        edited.bank_select = 0x10;
+        edited.program_change = 0x7F;
+        for (i, cc) in edited.custom_cc.iter_mut().enumerate() {
+            cc.number = i as u8 + 1;
+            cc.value = 0x80 - i as u8;
+        }
+        edited.mods = [
+            Mod::ADSREnv(ADSREnv {
+                dest: 1,
+                amount: 0xFF,
+                attack: 2,
+                decay: 3,
+                sustain: 4,
+                release: 5,
+            }),
+            Mod::LFO(LFO {
+                shape: 6,
+                dest: 2,
+                trigger_mode: 1,
+                freq: 0x40,
+                amount: 0x20,
+            }),
+            Mod::TrigEnv(TrigEnv {
+                dest: 3,
+                amount: 7,
+                attack: 8,
+                hold: 9,
+                decay: 10,
+                src: 11,
+            }),
+            Mod::TrackingEnv(TrackingEnv {
+                dest: 0x0F,
+                amount: 12,
+                src: 13,
+                lval: 14,
+                hval: 15,
+            }),
+        ];
+        let mut w = Writer::new(buf.clone());
+        w.seek(slot);
+        edited.write(&mut w, song.version);
+        let out = w.finish();
+        assert_eq!(&out[..slot], &buf[..slot]);
+        let reread = Song::from_bytes(out).unwrap();
+        assert_eq!(reread.instruments[6], Instrument::MIDIOut(edited.clone()));
+
+        // 2.x has no modulators for MIDI out
+        let template = Song::template_bytes(Version::new(2, 7, 0)).unwrap();
+        let mut w = Writer::new(template);
+        w.seek(layout::INSTRUMENTS);
+        edited.write(&mut w, Version::new(2, 7, 0));
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        let Instrument::MIDIOut(old) = &reread.instruments[0] else {
+            panic!("Instrument 0 should be MIDI out");
+        };
+        assert_eq!(old.custom_cc, edited.custom_cc);
+        assert_eq!(old.program_change, 0x7F);
+        assert_ne!(old.mods, edited.mods);
+    }
+
+    #[test]
+    fn test_effects_gaps() {
+        let instruments_end = layout::INSTRUMENTS + Song::N_INSTRUMENTS * layout::INSTRUMENT_SIZE;
+        let effects_end = layout::EFFECTS_SETTINGS + layout::EFFECTS_SETTINGS_SIZE;
+        assert_eq!(layout::EFFECTS_SETTINGS - instruments_end, 3);
+        assert_eq!(layout::MIDI_MAPPINGS - effects_end, 40);
+
+        let mut buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        buf[instruments_end..layout::EFFECTS_SETTINGS].copy_from_slice(&[1, 2, 3]);
+        buf[effects_end..layout::MIDI_MAPPINGS].fill(0xAA);
+        let mut song = Song::from_bytes(buf.clone()).unwrap();
+        assert_eq!(song, test_file());
+
+        song.effects_settings.chorus_mod_depth = 0x12;
+        song.effects_settings.reverb_width = 0x34;
+        song.midi_mappings[0].channel = 5;
+        let mut w = Writer::new(buf.clone());
+        song.write(&mut w).unwrap();
+        let out = w.finish();
+        assert_eq!(out[layout::EFFECTS_SETTINGS], 0x12);
+        assert_eq!(out[effects_end - 1], 0x34);
+        assert_eq!(out[layout::MIDI_MAPPINGS], 5);
+        // Everything from the instruments to the effects is unchanged, and so
+        // is the gap before the MIDI mappings
+        assert_eq!(
+            &out[layout::INSTRUMENTS..layout::EFFECTS_SETTINGS],
+            &buf[layout::INSTRUMENTS..layout::EFFECTS_SETTINGS]
+        );
+        assert_eq!(
+            &out[effects_end..layout::MIDI_MAPPINGS],
+            &buf[effects_end..layout::MIDI_MAPPINGS]
+        );
+        assert_eq!(Song::from_bytes(out).unwrap(), song);
+
+        let template = Song::template_bytes(song.version).unwrap();
+        assert!(template[instruments_end..layout::EFFECTS_SETTINGS]
+            .iter()
+            .all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_instruments_only() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        let instruments = Song::instruments_from_bytes(buf.clone()).unwrap();
+        assert_eq!(instruments, song.instruments);
+
+        // Truncated after the instruments is enough
+        let end = layout::INSTRUMENTS + Song::N_INSTRUMENTS * layout::INSTRUMENT_SIZE;
+        assert_eq!(
+            Song::instruments_from_bytes(buf[..end].to_vec()).unwrap(),
+            song.instruments
+        );
+        assert!(Song::instruments_from_bytes(buf[..end - 1].to_vec()).is_err());
+        assert!(Song::from_bytes(buf[..end].to_vec()).is_err());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_instruments_only() {
+        let mut f = std::fs::File::open("./examples/songs/DEFAULT.m8s").unwrap();
+        let instruments = Song::read_instruments_only(&mut f).unwrap();
+        assert!(instruments.iter().all(|i| *i == Instrument::None));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_errors() {
+        struct Failing;
+        impl std::io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disconnected"))
+            }
+        }
+        let err = Song::read(&mut Failing).unwrap_err();
+        assert!(matches!(&err, M8Error::Io(e) if e.to_string() == "disconnected"));
+        assert_eq!(err.to_string(), "IO error: disconnected");
+        assert!(matches!(
+            Instrument::read(&mut Failing),
+            Err(M8Error::Io(_))
+        ));
+        assert!(matches!(M8File::read(&mut Failing), Err(M8Error::Io(_))));
+
+        let err = Song::read(&mut &b"M8VERSION"[..]).unwrap_err();
+        assert!(matches!(&err, M8Error::Parse(e) if e.0.contains("not long enough")));
+        let err: Box<dyn std::error::Error> = Box::new(err);
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_set_directory() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let mut song = test_file();
+        song.set_directory("/Songs/moved/").unwrap();
+        let mut w = Writer::new(buf.clone());
+        song.write(&mut w).unwrap();
+        let out = w.finish();
+        assert_eq!(
+            &out[layout::DIRECTORY..layout::DIRECTORY + 14],
+            b"/Songs/moved/\0"
+        );
+        assert_eq!(Song::from_bytes(out).unwrap().directory, "/Songs/moved/");
+
+        let longest = "d".repeat(Song::MAX_DIRECTORY_LEN);
+        song.set_directory(&longest).unwrap();
+        let mut w = Writer::new(buf.clone());
+        song.write(&mut w).unwrap();
+        assert_eq!(Song::from_bytes(w.finish()).unwrap().directory, longest);
+
+        let too_long = "d".repeat(Song::MAX_DIRECTORY_LEN + 1);
+        assert!(song.set_directory(&too_long).is_err());
+        assert_eq!(song.directory, longest);
+        song.directory = too_long;
+        assert!(song.write(&mut Writer::new(buf.clone())).is_err());
+    }
+
+    #[test]
+    fn test_name_length() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let mut song = test_file();
+        song.name = "n".repeat(Song::MAX_NAME_LEN);
+        let mut w = Writer::new(buf.clone());
+        song.write(&mut w).unwrap();
+        assert_eq!(Song::from_bytes(w.finish()).unwrap().name, song.name);
+
+        song.name = "n".repeat(Song::MAX_NAME_LEN + 1);
+        assert!(song.write(&mut Writer::new(buf.clone())).is_err());
+        // 11 ASCII bytes and a 2 byte character: cutting it would split the character
+        song.name = "n".repeat(Song::MAX_NAME_LEN - 1) + "é";
+        assert!(song.write(&mut Writer::new(buf)).is_err());
+    }
+
+    #[test]
+    fn test_core_eq() {
+        let song = test_file();
+        let fm = &song.instruments[4];
+        let Instrument::FMSynth(mut s) = fm.clone() else {
+            panic!("Instrument 4 should be FM");
+        };
+        s.number = 0x20;
+        s.synth_params.mods[1] = Mod::DrumEnv(DrumEnv {
+            dest: 2,
+            amount: 3,
+            peak: 4,
+            body: 5,
+            decay: 6,
+        });
+        assert_ne!(*fm, Instrument::FMSynth(s.clone()));
+        assert!(fm.core_eq(&Instrument::FMSynth(s.clone())));
+        s.synth_params.volume ^= 1;
+        assert!(!fm.core_eq(&Instrument::FMSynth(s)));
+
+        let Instrument::MIDIOut(mut midi) = song.instruments[6].clone() else {
+            panic!("Instrument 6 should be MIDI out");
+        };
+        let original = Instrument::MIDIOut(midi.clone());
+        midi.mods[0] = Mod::TrackingEnv(TrackingEnv {
+            dest: 1,
+            amount: 2,
+            src: 3,
+            lval: 4,
+            hval: 5,
+        });
+        assert!(original.core_eq(&Instrument::MIDIOut(midi.clone())));
+        midi.channel ^= 1;
+        assert!(!original.core_eq(&Instrument::MIDIOut(midi)));
+
+        assert!(!fm.core_eq(&song.instruments[1]));
+        assert!(!fm.core_eq(&Instrument::None));
+        assert!(Instrument::None.core_eq(&Instrument::None));
+    }
+
+    #[test]
+    fn test_resolved_steps() {
+        let mut song = test_file();
+        let mut chain = song.chains[0x10].clone();
+        let plain = chain.resolved_steps(&song.phrases);
+        assert_eq!(plain.len(), 4);
+        for (phrase, step) in plain.iter().zip(chain.steps.iter()) {
+            assert_eq!(phrase, &song.phrases[step.phrase as usize]);
+        }
+
+        chain.steps[1].transpose = 0x0C;
+        chain.steps[2].transpose = -2i8 as u8;
+        chain.steps[3].phrase = 0xFE;
+        song.phrases[0xFE] = Phrase::default();
+        song.phrases[0xFE].steps[0].note = Note(0x7E);
+        song.phrases[0xFE].steps[1].note = Note(0x80);
+        chain.steps[3].transpose = 4;
+        let resolved = chain.resolved_steps(&song.phrases);
+        let source = &song.phrases[chain.steps[1].phrase as usize];
+        for (a, b) in resolved[1].steps.iter().zip(source.steps.iter()) {
+            assert_eq!(a.note, b.note.transpose(12));
+            assert_eq!(a.velocity, b.velocity);
+        }
+        let source = &song.phrases[chain.steps[2].phrase as usize];
+        assert_eq!(
+            resolved[2].steps[0].note,
+            source.steps[0].note.transpose(-2)
+        );
+        assert_eq!(resolved[3].steps[0].note, Note(Note::MAX));
+        assert_eq!(resolved[3].steps[1].note, Note(0x80));
+        assert!(resolved[3].steps[2].note.is_empty());
+
+        chain.steps[0] = ChainStep::default();
+        assert_eq!(chain.resolved_steps(&song.phrases).len(), 3);
+        assert_eq!(chain.resolved_steps(&song.phrases[..0x10]).len(), 0);
+    }
+
+    #[cfg(feature = "midi")]
+    #[test]
+    fn test_to_midi() {
+        let mut song = default_file();
+        song.tempo = 150.0;
+        let phrase = &mut song.phrases[0].steps;
+        phrase[0].note = Note(0x24);
+        phrase[0].velocity = 0x40;
+        phrase[4].note = Note(0x80);
+        phrase[8].note = Note(0x26);
+        phrase[12].velocity = 0x10;
+        song.chains[0].steps[0] = ChainStep {
+            phrase: 0,
+            transpose: 0x0C,
+        };
+        song.song.steps[0] = 0;
+
+        let midi = song.to_midi();
+        assert_eq!(&midi[..14], b"MThd\0\0\0\x06\0\x01\0\x09\0\x60");
+        let conductor = [
+            &b"MTrk\0\0\0\x16"[..],
+            b"\0\xFF\x03\x07DEFAULT",
+            b"\0\xFF\x51\x03\x06\x1A\x80",
+            b"\0\xFF\x2F\0",
+        ]
+        .concat();
+        assert_eq!(&midi[14..14 + conductor.len()], &conductor[..]);
+
+        let track1 = [
+            &b"MTrk\0\0\0\x20"[..],
+            b"\0\xFF\x03\x07Track 1",
+            // C-5 on at step 0, off at the note-off on step 4
+            b"\0\x90\x48\x40",
+            b"\x60\x80\x48\x40",
+            // D-5 at step 8 keeps the velocity, and ends with the phrase
+            b"\x60\x90\x4A\x40",
+            b"\x81\x40\x80\x4A\x40",
+            b"\0\xFF\x2F\0",
+        ]
+        .concat();
+        let rest = &midi[14 + conductor.len()..];
+        assert_eq!(&rest[..track1.len()], &track1[..]);
+
+        let empty_track = |n: u8| {
+            [
+                &b"MTrk\0\0\0\x0F"[..],
+                b"\0\xFF\x03\x07Track ",
+                &[b'0' + n],
+                b"\0\xFF\x2F\0",
+            ]
+            .concat()
+        };
+        let others: Vec<u8> = (2..=8).flat_map(empty_track).collect();
+        assert_eq!(&rest[track1.len()..], &others[..]);
+
+        // Every track of TEST-FILE plays something
+        let midi = test_file().to_midi();
+        let mut pos = 14;
+        let mut tracks = 0;
+        while pos < midi.len() {
+            assert_eq!(&midi[pos..pos + 4], b"MTrk");
+            let len = u32::from_be_bytes(midi[pos + 4..pos + 8].try_into().unwrap()) as usize;
+            pos += 8 + len;
+            tracks += 1;
+        }
+        assert_eq!(pos, midi.len());
+        assert_eq!(tracks, 9);
+    }
+
+    #[test]
+    fn test_phrase_csv() {
+        let song = test_file();
+        let v = song.version;
+        for phrase in song.phrases.iter().filter(|p| !p.is_empty()) {
+            let csv = phrase.to_csv();
+            assert_eq!(csv.lines().count(), 17);
+            assert_eq!(
+                Phrase::from_csv(phrase.number, v, &csv).as_ref(),
+                Ok(phrase)
+            );
+        }
+
+        let mut phrase = Phrase::from_csv(3, v, &Phrase::default().to_csv()).unwrap();
+        phrase.steps[0].note = Note(0x24);
+        phrase.steps[0].velocity = 0x7F;
+        phrase.steps[0].instrument = 2;
+        phrase.steps[0].fx2 = FX::new(v, "DEL", 0x40).unwrap();
+        phrase.steps[1].note = Note(0x80);
+        phrase.steps[2].fx3 = FX {
+            command: 0x7F,
+            value: 1,
+        };
+        let csv = phrase.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "row,note,velocity,instrument,fx1,fx1_value,fx2,fx2_value,fx3,fx3_value"
+        );
+        assert_eq!(lines[1], "0,C-4,7f,02,,,DEL,40,,");
+        assert_eq!(lines[2], "1,OFF,,,,,,,,");
+        assert_eq!(lines[3], "2,,,,,,,,7f,01");
+        assert_eq!(lines[16], "f,,,,,,,,,");
+        assert_eq!(Phrase::from_csv(3, v, &csv), Ok(phrase.clone()));
+
+        // Spreadsheets may add CRs and spaces
+        let edited = csv
+            .replace("0,C-4,7f,02", "0, D#5 ,10,02")
+            .replace('\n', "\r\n");
+        let parsed = Phrase::from_csv(3, v, &edited).unwrap();
+        assert_eq!(parsed.steps[0].note, Note(0x27 + 12));
+        assert_eq!(parsed.steps[0].velocity, 0x10);
+
+        assert!(Phrase::from_csv(3, v, &csv.replace("C-4", "H-4")).is_err());
+        assert!(Phrase::from_csv(3, v, &csv.replace("DEL", "XYZ")).is_err());
+        assert!(Phrase::from_csv(3, v, &csv.replace("1,OFF", "2,OFF")).is_err());
+        assert!(Phrase::from_csv(3, v, &lines[..16].join("\n")).is_err());
+        assert!(Phrase::from_csv(3, v, &lines[1..].join("\n")).is_err());
+
+        assert_eq!("C-1".parse(), Ok(Note(0)));
+        assert_eq!("G-B".parse(), Ok(Note(Note::MAX)));
+        assert!("G#B".parse::<Note>().is_err());
+        assert!("C-0".parse::<Note>().is_err());
+        assert_eq!("---".parse(), Ok(Note::default()));
+    }
+
+    #[test]
+    fn test_song_phrases_csv() {
+        let song = test_file();
+        let csv = song.export_phrases_csv();
+        assert_eq!(csv.lines().count(), 1 + 7 * 16);
+        assert!(csv.lines().nth(1).unwrap().starts_with("10,0,"));
+
+        let mut blank = test_file();
+        for phrase in blank.phrases.iter_mut() {
+            phrase.clear();
+        }
+        blank.import_phrases_csv(&csv).unwrap();
+        assert_eq!(blank.phrases, song.phrases);
+
+        let mut edited = test_file();
+        let csv2 = csv.replacen("10,0,", "fe,0,", 1);
+        assert!(edited.import_phrases_csv(&csv2).is_err());
+        let short: Vec<&str> = csv.lines().take(16).collect();
+        assert_eq!(
+            edited.import_phrases_csv(&short.join("\n")),
+            Err("Phrase 10 has 15 rows, expected 16".to_string())
+        );
+        let mut long: Vec<String> = csv.lines().map(String::from).collect();
+        long[17].replace_range(0..2, "10");
+        assert_eq!(
+            edited.import_phrases_csv(&long.join("\n")),
+            Err("Phrase 10 has 17 rows, expected 16".to_string())
+        );
+        assert_eq!(edited.phrases, song.phrases);
+
+        let moved: String = csv
+            .lines()
+            .take(17)
+            .map(|l| l.replacen("10,", "fe,", 1) + "\n")
+            .collect();
+        edited.import_phrases_csv(&moved).unwrap();
+        assert_eq!(edited.phrases[0xFE].steps, song.phrases[0x10].steps);
+        assert_eq!(edited.phrases[0xFE].number, 0xFE);
+    }
+
+    #[test]
+    fn test_song_patcher() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let song = test_file();
+        let mut patcher = SongPatcher::new(buf.clone()).unwrap();
+        assert_eq!(patcher.version(), song.version);
+
+        let mut step = song.phrases[0x10].steps[3].clone();
+        step.note = Note(0x30);
+        step.fx2 = FX::new(song.version, "DEL", 0x40).unwrap();
+        patcher.set_step(0x10, 3, &step).unwrap();
+        let chain_step = ChainStep {
+            phrase: 0x20,
+            transpose: 0x0C,
+        };
+        patcher.set_chain_step(0x10, 15, &chain_step).unwrap();
+        let mut table_step = TableStep::default();
+        table_step.set_transpose_semitones(-3);
+        patcher.set_table_step(0xFF, 0, &table_step).unwrap();
+        patcher.set_song_cell(7, 0x20, 0x10).unwrap();
+        patcher.set_instrument(1, &Instrument::None).unwrap();
+        let midi = song.instruments[6].clone();
+        patcher.set_instrument(0x7F, &midi).unwrap();
+
+        assert!(patcher.set_step(0xFF, 0, &step).is_err());
+        assert!(patcher.set_step(0, 16, &step).is_err());
+        assert!(patcher.set_song_cell(8, 0, 0).is_err());
+        assert!(patcher.set_step(0, usize::MAX, &step).is_err());
+        assert!(patcher.set_chain_step(0, usize::MAX, &chain_step).is_err());
+        assert!(patcher.set_table_step(0, usize::MAX, &table_step).is_err());
+        assert!(patcher.set_song_cell(0, usize::MAX, 0).is_err());
+        assert!(patcher.set_song_cell(usize::MAX, usize::MAX, 0).is_err());
+        assert!(patcher.set_instrument(0x80, &midi).is_err());
+        assert_eq!(patcher.bytes().len(), buf.len());
+
+        let mut expected = song.clone();
+        expected.phrases[0x10].steps[3] = step;
+        expected.chains[0x10].steps[15] = chain_step;
+        expected.tables[0xFF].steps[0] = table_step;
+        expected.song.steps[0x20 * 8 + 7] = 0x10;
+        expected.instruments[1] = Instrument::None;
+        expected.instruments[0x7F] = midi;
+        expected.instruments[0x7F].set_number(0x7F);
+        let patched = Song::from_bytes(patcher.finish()).unwrap();
+        assert_eq!(patched, expected);
+
+        assert!(SongPatcher::new(buf[..buf.len() - 1].to_vec()).is_err());
+        assert!(SongPatcher::new(vec![]).is_err());
+
+        // Every kind, into an empty slot and over its own
+        let mut patcher = SongPatcher::new(buf.clone()).unwrap();
+        let mut expected = song.clone();
+        for ix in 1..8 {
+            let mut instrument = song.instruments[ix].clone();
+            match &mut instrument {
+                Instrument::WavSynth(s) => s.warp = 0x42,
+                Instrument::MacroSynth(s) => s.synth_params.filter_cutoff = 0x42,
+                Instrument::Sampler(s) => s.set_sample_path("/Samples/other.wav").unwrap(),
+                Instrument::FMSynth(s) => s.operators[2].ratio = 0x42,
+                Instrument::HyperSynth(s) => s.chord[3] = 0x42,
+                Instrument::MIDIOut(s) => s.custom_cc[7].value = 0x42,
+                Instrument::External(s) => s.ccd.value = 0x42,
+                Instrument::None => unreachable!(),
+            }
+            patcher.set_instrument(ix as u8, &instrument).unwrap();
+            patcher
+                .set_instrument(0x70 + ix as u8, &instrument)
+                .unwrap();
+            expected.instruments[ix] = instrument.clone();
+            instrument.set_number(0x70 + ix as u8);
+            expected.instruments[0x70 + ix] = instrument;
+        }
+        assert_eq!(Song::from_bytes(patcher.finish()).unwrap(), expected);
+
+        let mut patcher =
+            SongPatcher::new(std::fs::read("./examples/songs/DEFAULT.m8s").unwrap()).unwrap();
+        assert!(patcher.set_instrument(0, &song.instruments[5]).is_err());
+        patcher.set_instrument(0, &song.instruments[4]).unwrap();
+    }
+
+    #[cfg(feature = "test-fixtures")]
+    #[test]
+    fn test_fixtures() {
+        let read = |name| std::fs::read(format!("./examples/songs/{}.m8s", name)).unwrap();
+        assert_eq!(fixtures::empty_song_bytes(), &read("DEFAULT")[..]);
+        assert_eq!(fixtures::test_song_bytes(), &read("TEST-FILE")[..]);
+        assert_eq!(fixtures::empty_song(), default_file());
+        assert_eq!(fixtures::test_song(), test_file());
+    }
+
+    #[test]
+    fn test_move_instrument_write() {
+        let buf = std::fs::read("./examples/songs/TEST-FILE.m8s").unwrap();
+        let mut song = test_file();
+        song.move_instrument(1, 40).unwrap();
+        let mut w = Writer::new(buf);
+        song.write(&mut w).unwrap();
+        let reread = Song::from_bytes(w.finish()).unwrap();
+        assert_eq!(reread.instruments[1], Instrument::None);
+        assert!(matches!(reread.instruments[40], Instrument::WavSynth(_)));
+        assert_eq!(reread.instruments[40], song.instruments[40]);
+        assert_eq!(reread, song);
+    }
+
+    #[test]
+    fn test_instrument_write() {
+        let song = test_file();
+        let v2 = Version::new(2, 7, 0);
+        let slot = |i: &Instrument, v| {
+            let mut w = Writer::new(vec![]);
+            i.write(&mut w, v).map(|_| w.finish())
+        };
+
+        // Written over an empty slot, each kind parses back as it was
+        for i in song.instruments.iter().take(8) {
+            let bytes = slot(i, song.version).unwrap();
+            assert_eq!(bytes.len(), layout::INSTRUMENT_SIZE);
+            let number = i.number().unwrap_or(0);
+            assert_eq!(
+                &Instrument::from_slot_bytes(&bytes, number, song.version).unwrap(),
+                i
+            );
+        }
+
+        // The FM synth has the 2.x modulators, so it can be written as 2.x too
+        let fm = &song.instruments[4];
+        let bytes = slot(fm, v2).unwrap();
+        assert_eq!(&Instrument::from_slot_bytes(&bytes, 4, v2).unwrap(), fm);
+        // Unlike the wavsynth, with its ADSR and drum envelopes, or the HyperSynth
+        assert!(slot(&song.instruments[1], v2).is_err());
+        assert!(slot(&song.instruments[5], v2).is_err());
+
+        let mut song = test_file();
+        song.version = v2;
+        assert!(song.write(&mut Writer::new(vec![])).is_err());
+    }
+
+    #[test]
+    fn test_v1_song() {
+        // DEFAULT.m8s as a 1.4 file: the same layout, and no scales
+        let v1_4 = Version::new(1, 4, 0);
+        let mut w = Writer::new(std::fs::read("./examples/songs/DEFAULT.m8s").unwrap());
+        v1_4.write(&mut w);
+        let mut buf = w.finish();
+        buf.truncate(Song::byte_size(v1_4));
+
+        let song = Song::from_bytes(buf).unwrap();
+        assert_eq!(song.version, v1_4);
+        assert_eq!(song.name, "DEFAULT");
+        assert!(Version::new(1, 0, 0).is_supported());
+        assert!(!Version::new(0, 9, 0).is_supported());
+    }
+
     #[test]
     fn test_mixer_reading() {
         let test_file = test_file();
@@ -0,0 +1,22 @@
+//! How many of each thing a song holds. These are fixed by the file format, and
+//! are the same for firmware 2.x and 3.x.
+
+pub use crate::fx::FX_COLUMNS;
+
+pub const N_PHRASES: usize = 255;
+pub const N_CHAINS: usize = 255;
+pub const N_INSTRUMENTS: usize = 128;
+pub const N_TABLES: usize = 256;
+pub const N_GROOVES: usize = 32;
+/// Firmware 2.5+. Older songs are given the default scales.
+pub const N_SCALES: usize = 16;
+pub const N_MIDI_MAPPINGS: usize = 128;
+
+/// Columns of the song screen.
+pub const N_TRACKS: usize = 8;
+/// Rows of the song screen.
+pub const N_SONG_ROWS: usize = 256;
+/// Steps in each chain, phrase and table, and in each groove.
+pub const N_STEPS: usize = 16;
+/// Modulators per instrument.
+pub const N_MODULATORS: usize = 4;
@@ -0,0 +1,124 @@
+use crate::*;
+
+use alloc::format;
+use alloc::vec::Vec;
+
+/// Ticks per quarter note. Each phrase step is a 16th note.
+const PPQ: u16 = 96;
+const STEP_TICKS: u32 = PPQ as u32 / 4;
+
+/// The velocity of a note whose step has none, before any step sets one.
+const DEFAULT_VELOCITY: u8 = 0x7F;
+
+/// The device shows note 0 as `C-1`, C in octave 1, which is MIDI note 24 when
+/// middle C is C4. Offsetting by that keeps the octaves: `C-4` becomes 60.
+const NOTE_OFFSET: u8 = 24;
+
+fn write_vlq(out: &mut Vec<u8>, mut v: u32) {
+    let mut groups = [0u8; 5];
+    let mut n = 0;
+    loop {
+        groups[n] = (v & 0x7F) as u8;
+        n += 1;
+        v >>= 7;
+        if v == 0 {
+            break;
+        }
+    }
+    for i in (0..n).rev() {
+        out.push(if i > 0 { groups[i] | 0x80 } else { groups[i] });
+    }
+}
+
+/// The events of one `MTrk` chunk, timed in absolute ticks.
+#[derive(Default)]
+struct TrackChunk {
+    data: Vec<u8>,
+    tick: u32,
+}
+
+impl TrackChunk {
+    fn event(&mut self, tick: u32, bytes: &[u8]) {
+        write_vlq(&mut self.data, tick - self.tick);
+        self.tick = tick;
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn meta(&mut self, tick: u32, ty: u8, bytes: &[u8]) {
+        self.event(tick, &[0xFF, ty]);
+        write_vlq(&mut self.data, bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn finish(mut self, tick: u32, out: &mut Vec<u8>) {
+        self.meta(tick, 0x2F, &[]);
+        out.extend_from_slice(b"MTrk");
+        out.extend_from_slice(&(self.data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.data);
+    }
+}
+
+impl Song {
+    /// The arrangement as a Standard MIDI File (format 1). This is a structural
+    /// export: it captures the notes and velocities each track plays, with chain
+    /// transposes applied, at the song's tempo. FX, grooves, instruments and
+    /// modulation are ignored.
+    ///
+    /// The first MIDI track holds the song's name and tempo. Then each of the 8
+    /// M8 tracks gets a MIDI track on the channel of the same number (track 1 on
+    /// channel 1). A track plays its non-empty song rows once, top to bottom, at
+    /// a 16th note per step. A note sounds until the track's next note or
+    /// note-off. A step without a velocity uses the previous one on the track, and
+    /// a velocity of 0 is silent. Note numbers keep the M8's octaves: `C-4` is
+    /// MIDI note 60.
+    pub fn to_midi(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"MThd");
+        out.extend_from_slice(&6u32.to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&(1 + limits::N_TRACKS as u16).to_be_bytes());
+        out.extend_from_slice(&PPQ.to_be_bytes());
+
+        let mut conductor = TrackChunk::default();
+        conductor.meta(0, 0x03, self.name.as_bytes());
+        let tempo = if self.tempo > 0.0 { self.tempo } else { 120.0 };
+        let us_per_quarter = ((60_000_000.0 / tempo) as u32).min(0xFF_FFFF);
+        conductor.meta(0, 0x51, &us_per_quarter.to_be_bytes()[1..]);
+        conductor.finish(0, &mut out);
+
+        for track in self.tracks() {
+            let channel = track.number() as u8;
+            let mut chunk = TrackChunk::default();
+            chunk.meta(0, 0x03, format!("Track {}", track.number() + 1).as_bytes());
+
+            let mut tick = 0;
+            let mut velocity = DEFAULT_VELOCITY;
+            let mut sounding: Option<u8> = None;
+            for (_, chain_step, phrase) in track.phrases() {
+                for step in &phrase.steps {
+                    let note = step.note.transpose(chain_step.transpose as i8);
+                    if !note.is_empty() {
+                        if let Some(key) = sounding.take() {
+                            chunk.event(tick, &[0x80 | channel, key, 0x40]);
+                        }
+                        if step.velocity != 255 {
+                            // Songs may hold velocities above 0x7F
+                            velocity = step.velocity.min(0x7F);
+                        }
+                        if !note.is_off() && velocity > 0 {
+                            let key = note.0.saturating_add(NOTE_OFFSET).min(0x7F);
+                            chunk.event(tick, &[0x90 | channel, key, velocity]);
+                            sounding = Some(key);
+                        }
+                    }
+                    tick += STEP_TICKS;
+                }
+            }
+            if let Some(key) = sounding {
+                chunk.event(tick, &[0x80 | channel, key, 0x40]);
+            }
+            chunk.finish(tick, &mut out);
+        }
+        out
+    }
+}
@@ -0,0 +1,140 @@
+use crate::*;
+
+/// The offset of slot `ix` of a section of `n` slots, if `version`'s song layout
+/// is known.
+fn slot(version: Version, base: usize, size: usize, ix: usize, n: usize) -> Option<usize> {
+    (version.is_supported() && ix < n).then(|| base + ix * size)
+}
+
+/// Byte offsets into a song file, for patching it in place with
+/// [`Writer::seek`] rather than rewriting the whole song. They're version
+/// dependent, so each takes the firmware version of the file, and returns `None`
+/// for versions whose layout isn't known or for slots that are out of range.
+/// Offsets within a slot (e.g. [`Phrase::step_offset`]) are added on:
+/// phrase 12, step 3, FX 2 is at
+/// `Song::phrase_offset(v, 12)? + Phrase::step_offset(3)? + Step::fx_offset(1)?`.
+impl Song {
+    pub fn groove_offset(version: Version, ix: usize) -> Option<usize> {
+        slot(
+            version,
+            layout::GROOVES,
+            layout::GROOVE_SIZE,
+            ix,
+            Self::N_GROOVES,
+        )
+    }
+
+    /// The chain number on song row `row` of track `track`.
+    pub fn song_step_offset(version: Version, row: usize, track: usize) -> Option<usize> {
+        if track >= limits::N_TRACKS || row >= limits::N_SONG_ROWS {
+            return None;
+        }
+        slot(
+            version,
+            layout::SONG,
+            1,
+            row * limits::N_TRACKS + track,
+            layout::SONG_SIZE,
+        )
+    }
+
+    pub fn phrase_offset(version: Version, ix: usize) -> Option<usize> {
+        slot(
+            version,
+            layout::PHRASES,
+            layout::PHRASE_SIZE,
+            ix,
+            Self::N_PHRASES,
+        )
+    }
+
+    pub fn chain_offset(version: Version, ix: usize) -> Option<usize> {
+        slot(
+            version,
+            layout::CHAINS,
+            layout::CHAIN_SIZE,
+            ix,
+            Self::N_CHAINS,
+        )
+    }
+
+    pub fn table_offset(version: Version, ix: usize) -> Option<usize> {
+        slot(
+            version,
+            layout::TABLES,
+            layout::TABLE_SIZE,
+            ix,
+            Self::N_TABLES,
+        )
+    }
+
+    /// The instrument slot's layout within also depends on the version, see
+    /// [`layout::instrument`].
+    pub fn instrument_offset(version: Version, ix: usize) -> Option<usize> {
+        slot(
+            version,
+            layout::INSTRUMENTS,
+            layout::INSTRUMENT_SIZE,
+            ix,
+            Self::N_INSTRUMENTS,
+        )
+    }
+
+    pub fn midi_mapping_offset(version: Version, ix: usize) -> Option<usize> {
+        slot(
+            version,
+            layout::MIDI_MAPPINGS,
+            layout::MIDI_MAPPING_SIZE,
+            ix,
+            Self::N_MIDI_MAPPINGS,
+        )
+    }
+
+    /// `None` before firmware 2.5, which doesn't store scales.
+    pub fn scale_offset(version: Version, ix: usize) -> Option<usize> {
+        let n = if version.at_least(2, 5) {
+            Self::N_SCALES
+        } else {
+            0
+        };
+        slot(version, layout::SCALES, layout::SCALE_SIZE, ix, n)
+    }
+}
+
+impl Chain {
+    /// The offset of step `ix` from the start of the chain. Each step is its
+    /// phrase then its transpose.
+    pub fn step_offset(ix: usize) -> Option<usize> {
+        (ix < limits::N_STEPS).then(|| ix * layout::CHAIN_STEP_SIZE)
+    }
+}
+
+impl Phrase {
+    /// The offset of step `ix` from the start of the phrase.
+    pub fn step_offset(ix: usize) -> Option<usize> {
+        (ix < limits::N_STEPS).then(|| ix * layout::STEP_SIZE)
+    }
+}
+
+impl Step {
+    /// The offset of FX column `slot` (0-2) from the start of the step, after the
+    /// note, velocity and instrument. The command comes before the value.
+    pub fn fx_offset(slot: usize) -> Option<usize> {
+        (slot < limits::FX_COLUMNS).then(|| 3 + slot * layout::FX_SIZE)
+    }
+}
+
+impl Table {
+    /// The offset of step `ix` from the start of the table.
+    pub fn step_offset(ix: usize) -> Option<usize> {
+        (ix < limits::N_STEPS).then(|| ix * layout::TABLE_STEP_SIZE)
+    }
+}
+
+impl TableStep {
+    /// The offset of FX column `slot` (0-2) from the start of the step, after the
+    /// transpose and velocity. The command comes before the value.
+    pub fn fx_offset(slot: usize) -> Option<usize> {
+        (slot < limits::FX_COLUMNS).then(|| 2 + slot * layout::FX_SIZE)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::*;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// Edits the bytes of a song file one field at a time, for tools that change a
+/// few steps repeatedly and don't want to parse or rewrite the whole song on
+/// every edit. Each setter writes only the bytes of what it sets, at the offsets
+/// from [`Song::phrase_offset`] and friends. Everything else is left as it is.
+pub struct SongPatcher {
+    writer: Writer,
+    version: Version,
+}
+
+impl SongPatcher {
+    /// Fails if `bytes` isn't a whole song file of a supported firmware version.
+    pub fn new(bytes: Vec<u8>) -> Result<Self> {
+        if bytes.len() < Version::SIZE {
+            return Err(ParseError(
+                "File is not long enough to be a M8 song".to_string(),
+            ));
+        }
+        let version = Version::from_reader(&Reader::new(bytes[..Version::SIZE].to_vec()))?;
+        if !version.is_supported() {
+            return Err(ParseError(format!(
+                "Unsupported firmware version {}",
+                version
+            )));
+        }
+        if bytes.len() != Song::byte_size(version) {
+            return Err(ParseError(format!(
+                "File is {:#x} bytes, a {} song is {:#x}",
+                bytes.len(),
+                version,
+                Song::byte_size(version)
+            )));
+        }
+        Ok(Self {
+            writer: Writer::new(bytes),
+            version,
+        })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Set step `step` (0-15) of phrase `phrase`.
+    pub fn set_step(
+        &mut self,
+        phrase: u8,
+        step: usize,
+        value: &Step,
+    ) -> core::result::Result<(), String> {
+        let offset = Song::phrase_offset(self.version, phrase as usize)
+            .zip(Phrase::step_offset(step))
+            .ok_or_else(|| format!("Phrase {:02x} step {} is out of range", phrase, step))?;
+        self.writer.seek(offset.0 + offset.1);
+        value.write(&mut self.writer);
+        Ok(())
+    }
+
+    /// Set step `step` (0-15) of chain `chain`.
+    pub fn set_chain_step(
+        &mut self,
+        chain: u8,
+        step: usize,
+        value: &ChainStep,
+    ) -> core::result::Result<(), String> {
+        let offset = Song::chain_offset(self.version, chain as usize)
+            .zip(Chain::step_offset(step))
+            .ok_or_else(|| format!("Chain {:02x} step {} is out of range", chain, step))?;
+        self.writer.seek(offset.0 + offset.1);
+        value.write(&mut self.writer);
+        Ok(())
+    }
+
+    /// Set step `step` (0-15) of table `table`.
+    pub fn set_table_step(
+        &mut self,
+        table: u8,
+        step: usize,
+        value: &TableStep,
+    ) -> core::result::Result<(), String> {
+        let offset = Song::table_offset(self.version, table as usize)
+            .zip(Table::step_offset(step))
+            .ok_or_else(|| format!("Table {:02x} step {} is out of range", table, step))?;
+        self.writer.seek(offset.0 + offset.1);
+        value.write(&mut self.writer);
+        Ok(())
+    }
+
+    /// Set the chain on song row `row` of track `track`. 255 empties the cell.
+    pub fn set_song_cell(
+        &mut self,
+        track: usize,
+        row: usize,
+        chain: u8,
+    ) -> core::result::Result<(), String> {
+        let offset = Song::song_step_offset(self.version, row, track)
+            .ok_or_else(|| format!("Song row {:02x} track {} is out of range", row, track))?;
+        self.writer.seek(offset);
+        self.writer.write(chain);
+        Ok(())
+    }
+
+    /// Set instrument slot `ix`, as [`Instrument::write`] does.
+    pub fn set_instrument(
+        &mut self,
+        ix: u8,
+        instrument: &Instrument,
+    ) -> core::result::Result<(), String> {
+        let offset = Song::instrument_offset(self.version, ix as usize)
+            .ok_or_else(|| format!("Instrument {:02x} is out of range", ix))?;
+        self.writer.seek(offset);
+        instrument.write(&mut self.writer, self.version)
+    }
+
+    /// The patched file so far.
+    pub fn bytes(&self) -> &[u8] {
+        self.writer.as_bytes()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.writer.finish()
+    }
+}
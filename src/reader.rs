@@ -1,6 +1,9 @@
-use std::cell::RefCell;
-use std::fmt;
-use std::rc::Rc;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
 
 #[derive(PartialEq, Debug)]
 pub struct ParseError(pub String);
@@ -11,10 +14,66 @@ impl fmt::Display for ParseError {
     }
 }
 
-impl std::error::Error for ParseError {}
+impl core::error::Error for ParseError {}
 
-pub type Result<T> = std::result::Result<T, ParseError>;
+pub type Result<T> = core::result::Result<T, ParseError>;
 
+/// An error reading an M8 file from a `std::io::Read`er: either the data
+/// couldn't be read, or it couldn't be parsed.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum M8Error {
+    Io(std::io::Error),
+    Parse(ParseError),
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for M8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "IO error: {}", e),
+            Self::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for M8Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for M8Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ParseError> for M8Error {
+    fn from(e: ParseError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+/// The result of the `read` entry points, which do IO. The `from_bytes` ones
+/// only parse, and fail with a plain [`ParseError`].
+#[cfg(feature = "std")]
+pub type M8Result<T> = core::result::Result<T, M8Error>;
+
+#[cfg(feature = "std")]
+pub(crate) fn read_to_vec(reader: &mut impl std::io::Read) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads M8 data out of a buffer, tracking the current position.
 pub struct Reader {
     buffer: Vec<u8>,
     position: Rc<RefCell<usize>>,
@@ -43,16 +102,37 @@ impl Reader {
         bs
     }
 
+    /// Like `read_bytes`, but the bytes are copied out so the reader isn't
+    /// borrowed.
+    pub fn read_vec(&self, n: usize) -> Vec<u8> {
+        self.read_bytes(n).to_vec()
+    }
+
+    /// Read a fixed-size field, failing rather than panicking if the buffer ends
+    /// first. The position is left alone on failure.
+    pub fn read_array<const N: usize>(&self) -> Result<[u8; N]> {
+        let p = self.pos();
+        if p + N > self.buffer.len() {
+            return Err(ParseError(format!(
+                "Can't read {} bytes at {:#x}, the data ends at {:#x}",
+                N,
+                p,
+                self.buffer.len()
+            )));
+        }
+        let mut bs = [0; N];
+        bs.copy_from_slice(self.read_bytes(N));
+        Ok(bs)
+    }
+
     pub fn read_bool(&self) -> bool {
         self.read() == 1
     }
 
     pub fn read_string(&self, n: usize) -> String {
         let b = self.read_bytes(n);
-        let end = b.iter().position(|&x| x == 0 || x == 255).unwrap_or(0);
-        std::str::from_utf8(&b[0..end])
-            .expect("invalid utf-8 sequence in string")
-            .to_string()
+        let end = b.iter().position(|&x| x == 0 || x == 255).unwrap_or(n);
+        String::from_utf8_lossy(&b[0..end]).to_string()
     }
 
     pub fn pos(&self) -> usize {
@@ -62,4 +142,39 @@ impl Reader {
     pub fn set_pos(&self, n: usize) {
         *self.position.borrow_mut() = n;
     }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Like `set_pos`, but fails if `n` is past the end of the buffer.
+    pub fn try_set_pos(&self, n: usize) -> Result<()> {
+        if n > self.buffer.len() {
+            return Err(ParseError(format!(
+                "Position {:#x} is past the end of the data ({:#x})",
+                n,
+                self.buffer.len()
+            )));
+        }
+        self.set_pos(n);
+        Ok(())
+    }
+
+    /// Move forward `n` bytes, failing if that passes the end of the buffer.
+    pub fn advance(&self, n: usize) -> Result<()> {
+        self.try_set_pos(self.pos() + n)
+    }
+
+    /// Move back `n` bytes, failing if that passes the start of the buffer.
+    pub fn rewind(&self, n: usize) -> Result<()> {
+        let pos = self.pos().checked_sub(n).ok_or_else(|| {
+            ParseError(format!("Can't rewind {} bytes from {:#x}", n, self.pos()))
+        })?;
+        self.set_pos(pos);
+        Ok(())
+    }
 }
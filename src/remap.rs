@@ -0,0 +1,178 @@
+use crate::*;
+
+use alloc::format;
+use alloc::string::String;
+
+pub(crate) fn check_range(what: &str, ix: usize, n: usize) -> core::result::Result<(), String> {
+    if ix >= n {
+        Err(format!("{} {:02x} is out of range", what, ix))
+    } else {
+        Ok(())
+    }
+}
+
+/// Maps `a` to `b`, and `b` to `a` when `swap` is set. Other slots are unchanged.
+fn exchange(a: u8, b: u8, swap: bool) -> impl Fn(u8) -> u8 {
+    move |x| {
+        if x == a {
+            b
+        } else if swap && x == b {
+            a
+        } else {
+            x
+        }
+    }
+}
+
+impl Song {
+    /// Move instrument `from` to the empty slot `to`, along with the table it
+    /// owns. Phrase steps that play it, and TBL/TBX commands that reference its
+    /// table, are updated to match. Fails if `to` holds an instrument or a table.
+    pub fn move_instrument(&mut self, from: u8, to: u8) -> core::result::Result<(), String> {
+        check_range("Instrument", from as usize, Self::N_INSTRUMENTS)?;
+        check_range("Instrument", to as usize, Self::N_INSTRUMENTS)?;
+        if self.instruments[to as usize] != Instrument::None
+            || self.instrument_has_table(to as usize)
+        {
+            return Err(format!("Instrument {:02x} is in use", to));
+        }
+        self.exchange_instruments(from, to, false);
+        Ok(())
+    }
+
+    /// Move chain `from` to `to`, updating the song steps that reference it.
+    /// Fails if `to` is in use.
+    pub fn move_chain(&mut self, from: u8, to: u8) -> core::result::Result<(), String> {
+        check_range("Chain", from as usize, Self::N_CHAINS)?;
+        check_range("Chain", to as usize, Self::N_CHAINS)?;
+        if !self.chain_is_free(to as usize) {
+            return Err(format!("Chain {:02x} is in use", to));
+        }
+        self.exchange_chains(from, to, false);
+        Ok(())
+    }
+
+    /// Move phrase `from` to `to`, updating the chain steps that reference it.
+    /// Fails if `to` is in use.
+    pub fn move_phrase(&mut self, from: u8, to: u8) -> core::result::Result<(), String> {
+        check_range("Phrase", from as usize, Self::N_PHRASES)?;
+        check_range("Phrase", to as usize, Self::N_PHRASES)?;
+        if !self.phrase_is_free(to as usize) {
+            return Err(format!("Phrase {:02x} is in use", to));
+        }
+        self.exchange_phrases(from, to, false);
+        Ok(())
+    }
+
+    /// Move table `from` to `to`, updating the TBL/TBX commands that reference
+    /// it. Fails if table `to` isn't empty. Note that the table then belongs to
+    /// instrument `to`, if there is one.
+    pub fn move_table(&mut self, from: u8, to: u8) -> core::result::Result<(), String> {
+        check_range("Table", from as usize, Self::N_TABLES)?;
+        check_range("Table", to as usize, Self::N_TABLES)?;
+        if !self.tables[to as usize].is_empty() {
+            return Err(format!("Table {:02x} is in use", to));
+        }
+        self.exchange_tables(from, to, false);
+        Ok(())
+    }
+
+    /// Swap instruments `a` and `b` along with their tables, rewriting every
+    /// reference so that the song sounds the same.
+    pub fn swap_instruments(&mut self, a: u8, b: u8) -> core::result::Result<(), String> {
+        check_range("Instrument", a as usize, Self::N_INSTRUMENTS)?;
+        check_range("Instrument", b as usize, Self::N_INSTRUMENTS)?;
+        self.exchange_instruments(a, b, true);
+        Ok(())
+    }
+
+    /// Swap chains `a` and `b`, rewriting the song steps so that the song sounds
+    /// the same.
+    pub fn swap_chains(&mut self, a: u8, b: u8) -> core::result::Result<(), String> {
+        check_range("Chain", a as usize, Self::N_CHAINS)?;
+        check_range("Chain", b as usize, Self::N_CHAINS)?;
+        self.exchange_chains(a, b, true);
+        Ok(())
+    }
+
+    /// Swap phrases `a` and `b`, rewriting the chain steps so that the song
+    /// sounds the same.
+    pub fn swap_phrases(&mut self, a: u8, b: u8) -> core::result::Result<(), String> {
+        check_range("Phrase", a as usize, Self::N_PHRASES)?;
+        check_range("Phrase", b as usize, Self::N_PHRASES)?;
+        self.exchange_phrases(a, b, true);
+        Ok(())
+    }
+
+    // Without `swap`, `a` moves to `b` and the slot it leaves is cleared
+
+    fn exchange_instruments(&mut self, a: u8, b: u8, swap: bool) {
+        let (ai, bi) = (a as usize, b as usize);
+        self.instruments.swap(ai, bi);
+        self.instruments[ai].set_number(a);
+        self.instruments[bi].set_number(b);
+        if !swap {
+            self.instruments[ai].clear();
+        }
+        let f = exchange(a, b, swap);
+        for step in self.phrases.iter_mut().flat_map(|p| p.steps.iter_mut()) {
+            step.instrument = f(step.instrument);
+        }
+        self.exchange_tables(a, b, swap);
+    }
+
+    fn exchange_chains(&mut self, a: u8, b: u8, swap: bool) {
+        let (ai, bi) = (a as usize, b as usize);
+        self.chains.swap(ai, bi);
+        self.chains[ai].number = a;
+        self.chains[bi].number = b;
+        if !swap {
+            self.chains[ai].clear();
+        }
+        let f = exchange(a, b, swap);
+        for step in self.song.steps.iter_mut() {
+            *step = f(*step);
+        }
+    }
+
+    fn exchange_phrases(&mut self, a: u8, b: u8, swap: bool) {
+        let (ai, bi) = (a as usize, b as usize);
+        self.phrases.swap(ai, bi);
+        self.phrases[ai].number = a;
+        self.phrases[bi].number = b;
+        if !swap {
+            self.phrases[ai].clear();
+        }
+        let f = exchange(a, b, swap);
+        for step in self.chains.iter_mut().flat_map(|c| c.steps.iter_mut()) {
+            step.phrase = f(step.phrase);
+        }
+    }
+
+    fn exchange_tables(&mut self, a: u8, b: u8, swap: bool) {
+        let (ai, bi) = (a as usize, b as usize);
+        self.tables.swap(ai, bi);
+        self.tables[ai].number = a;
+        self.tables[bi].number = b;
+        if !swap {
+            self.tables[ai].clear();
+        }
+
+        let version = self.version;
+        let f = exchange(a, b, swap);
+        let fx = self
+            .phrases
+            .iter_mut()
+            .flat_map(|p| p.steps.iter_mut().flat_map(|s| s.fx_iter_mut()))
+            .chain(
+                self.tables
+                    .iter_mut()
+                    .flat_map(|t| t.steps.iter_mut().flat_map(|s| s.fx_iter_mut())),
+            );
+        let references_table =
+            |fx: &&mut FX| matches!(fx.referenced_slot(version), Some((RefKind::Table, _)));
+        for fx in fx.filter(references_table) {
+            fx.value = f(fx.value);
+        }
+    }
+}
@@ -1,23 +1,33 @@
 use crate::reader::*;
 use crate::version::*;
+use crate::writer::*;
 
-use std::fmt;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
 
 use arr_macro::arr;
 use byteorder::{ByteOrder, LittleEndian};
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Hash)]
 pub struct Scale {
     pub number: u8,
     pub name: String,
     pub notes: [NoteOffset; 12], // Offsets for notes C-B
 }
 impl Scale {
-    const SIZE: usize = 32;
+    pub(crate) const SIZE: usize = 42;
 
-    pub fn read(reader: &mut impl std::io::Read) -> Result<Self> {
-        let mut buf: Vec<u8> = vec![];
-        reader.read_to_end(&mut buf).unwrap();
+    #[cfg(feature = "std")]
+    pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes(buf)?)
+    }
+
+    /// Parse the full contents of a file that's already in memory.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
         let len = buf.len();
         let reader = Reader::new(buf);
 
@@ -30,6 +40,43 @@ impl Scale {
         Self::from_reader(&reader, 0)
     }
 
+    /// The sixteen scales a new song starts with.
+    pub fn presets() -> Vec<Self> {
+        const PRESETS: [(&str, u16); 16] = [
+            ("CHROMATIC", 0xFFF),
+            ("MAJOR", 0xAB5),
+            ("MINOR", 0x5AD),
+            ("DORIAN", 0x6AD),
+            ("LYDIAN", 0xAD5),
+            ("MIXOLYDIAN", 0x6B5),
+            ("LOCRIAN", 0x56B),
+            ("PENTATONIC", 0x295),
+            ("MINOR PENTATONIC", 0x4A9),
+            ("MAJOR BLUES", 0x29D),
+            ("MINOR BLUES", 0x4E9),
+            ("ROMANIAN MINOR", 0x6CD),
+            ("HIRAJOSHI", 0x18D),
+            ("KUMOIJOSHI", 0x1A3),
+            ("IN-SEN", 0x4A3),
+            ("IWATO", 0x463),
+        ];
+        PRESETS
+            .iter()
+            .enumerate()
+            .map(|(i, (name, map))| {
+                let mut notes = arr![NoteOffset::default(); 12];
+                for (n, note) in notes.iter_mut().enumerate() {
+                    note.enabled = ((map >> n) & 0x1) == 1;
+                }
+                Self {
+                    number: i as u8,
+                    name: name.to_string(),
+                    notes,
+                }
+            })
+            .collect()
+    }
+
     pub(crate) fn from_reader(reader: &Reader, number: u8) -> Result<Self> {
         let map = LittleEndian::read_u16(reader.read_bytes(2));
         let mut notes = arr![NoteOffset::default(); 12];
@@ -47,6 +94,21 @@ impl Scale {
             notes,
         })
     }
+
+    pub(crate) fn write(&self, w: &mut Writer) {
+        let map = self
+            .notes
+            .iter()
+            .enumerate()
+            .fold(0u16, |map, (i, note)| map | ((note.enabled as u16) << i));
+        w.write_bytes(&map.to_le_bytes());
+        for note in self.notes.iter() {
+            let cents = (note.semitones * 100.0 + 0.5) as u16;
+            w.write((cents / 100) as u8);
+            w.write((cents % 100) as u8);
+        }
+        w.write_string(&self.name, 16);
+    }
 }
 
 impl Default for Scale {
@@ -98,6 +160,12 @@ pub struct NoteOffset {
     pub enabled: bool,
     pub semitones: f32, // Semitones.cents: -24.0-24.0
 }
+impl core::hash::Hash for NoteOffset {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.enabled.hash(state);
+        self.semitones.to_bits().hash(state);
+    }
+}
 impl NoteOffset {
     fn default() -> Self {
         Self {
@@ -0,0 +1,119 @@
+use crate::reader::*;
+use crate::version::*;
+use crate::writer::*;
+use crate::*;
+
+use alloc::vec::Vec;
+
+/// A fixed size part of a song that repeats, like chains, phrases and tables.
+pub trait Section: Sized {
+    const SIZE: usize;
+
+    /// `number` is the slot being read. Sections that don't store it ignore it,
+    /// as do those that don't depend on `version`.
+    fn read(reader: &Reader, number: u8, version: Version) -> Result<Self>;
+    fn write(&self, w: &mut Writer);
+}
+
+/// Read `n` sections starting at `offset`, numbered from 0.
+pub fn read_sections<T: Section>(
+    reader: &Reader,
+    offset: usize,
+    n: usize,
+    version: Version,
+) -> Result<Vec<T>> {
+    reader.try_set_pos(offset)?;
+    (0..n).map(|i| T::read(reader, i as u8, version)).collect()
+}
+
+/// Write `sections` back to back, starting at `offset`.
+pub fn write_sections<T: Section>(w: &mut Writer, offset: usize, sections: &[T]) {
+    w.seek(offset);
+    for s in sections {
+        s.write(w);
+    }
+}
+
+impl Section for ChainStep {
+    const SIZE: usize = layout::CHAIN_STEP_SIZE;
+
+    fn read(reader: &Reader, _number: u8, _version: Version) -> Result<Self> {
+        Self::from_reader(reader)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
+
+impl Section for Chain {
+    const SIZE: usize = layout::CHAIN_SIZE;
+
+    fn read(reader: &Reader, number: u8, _version: Version) -> Result<Self> {
+        Self::from_reader(reader, number)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
+
+impl Section for Step {
+    const SIZE: usize = layout::STEP_SIZE;
+
+    fn read(reader: &Reader, _number: u8, _version: Version) -> Result<Self> {
+        Self::from_reader(reader)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
+
+impl Section for Phrase {
+    const SIZE: usize = layout::PHRASE_SIZE;
+
+    fn read(reader: &Reader, number: u8, version: Version) -> Result<Self> {
+        Self::from_reader(reader, number, version)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
+
+impl Section for TableStep {
+    const SIZE: usize = layout::TABLE_STEP_SIZE;
+
+    fn read(reader: &Reader, _number: u8, _version: Version) -> Result<Self> {
+        Self::from_reader(reader)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
+
+impl Section for Table {
+    const SIZE: usize = layout::TABLE_SIZE;
+
+    fn read(reader: &Reader, number: u8, version: Version) -> Result<Self> {
+        Self::from_reader(reader, number, version)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
+
+impl Section for Groove {
+    const SIZE: usize = layout::GROOVE_SIZE;
+
+    fn read(reader: &Reader, number: u8, _version: Version) -> Result<Self> {
+        Self::from_reader(reader, number)
+    }
+
+    fn write(&self, w: &mut Writer) {
+        self.write(w)
+    }
+}
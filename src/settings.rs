@@ -1,6 +1,10 @@
 use crate::reader::*;
+use crate::writer::*;
 
-#[derive(PartialEq, Debug, Clone)]
+use alloc::format;
+use alloc::string::String;
+
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct MidiSettings {
     pub receive_sync: bool,
     pub receive_transport: u8,
@@ -16,6 +20,25 @@ pub struct MidiSettings {
     pub track_input_program_change: bool,
     pub track_input_mode: u8,
 }
+impl Default for MidiSettings {
+    fn default() -> Self {
+        Self {
+            receive_sync: false,
+            receive_transport: 0,
+            send_sync: false,
+            send_transport: 0,
+            record_note_channel: 9,
+            record_note_velocity: true,
+            record_note_delay_kill_commands: 0,
+            control_map_channel: 17,
+            song_row_cue_channel: 11,
+            track_input_channel: [1, 2, 3, 4, 5, 6, 7, 8],
+            track_input_intrument: [0; 8],
+            track_input_program_change: true,
+            track_input_mode: 1,
+        }
+    }
+}
 impl MidiSettings {
     pub(crate) fn from_reader(reader: &Reader) -> Result<Self> {
         Ok(Self {
@@ -28,15 +51,35 @@ impl MidiSettings {
             record_note_delay_kill_commands: reader.read(),
             control_map_channel: reader.read(),
             song_row_cue_channel: reader.read(),
-            track_input_channel: reader.read_bytes(8).try_into().unwrap(),
-            track_input_intrument: reader.read_bytes(8).try_into().unwrap(),
+            track_input_channel: reader.read_array()?,
+            track_input_intrument: reader.read_array()?,
             track_input_program_change: reader.read_bool(),
             track_input_mode: reader.read(),
         })
     }
+
+    pub(crate) fn write(&self, w: &mut Writer) {
+        w.write_bool(self.receive_sync);
+        w.write(self.receive_transport);
+        w.write_bool(self.send_sync);
+        w.write(self.send_transport);
+        w.write(self.record_note_channel);
+        w.write_bool(self.record_note_velocity);
+        w.write(self.record_note_delay_kill_commands);
+        w.write(self.control_map_channel);
+        w.write(self.song_row_cue_channel);
+        w.write_bytes(&self.track_input_channel);
+        w.write_bytes(&self.track_input_intrument);
+        w.write_bool(self.track_input_program_change);
+        w.write(self.track_input_mode);
+    }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+/// The mixer screen's levels. Track mute and solo aren't included: nothing in
+/// the mixer section is known to hold them, so they're treated as performance
+/// state that isn't saved with the song. The last 4 bytes of the section aren't
+/// parsed and are left as they are on write.
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct MixerSettings {
     pub master_volume: u8,
     pub master_limit: u8,
@@ -50,11 +93,28 @@ pub struct MixerSettings {
     pub dj_peak: u8,
     pub dj_filter_type: u8,
 }
+impl Default for MixerSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 0xE0,
+            master_limit: 0,
+            track_volume: [0xE0; 8],
+            chorus_volume: 0xE0,
+            delay_volume: 0xE0,
+            reverb_volume: 0xE0,
+            analog_input: AnalogInputSettings::Stereo(InputMixerSettings::default()),
+            usb_input: InputMixerSettings::default(),
+            dj_filter: 0x80,
+            dj_peak: 0x80,
+            dj_filter_type: 0x3A,
+        }
+    }
+}
 impl MixerSettings {
     pub(crate) fn from_reader(reader: &Reader) -> Result<Self> {
         let master_volume = reader.read();
         let master_limit = reader.read();
-        let track_volume: [u8; 8] = reader.read_bytes(8).try_into().unwrap();
+        let track_volume = reader.read_array()?;
         let chorus_volume = reader.read();
         let delay_volume = reader.read();
         let reverb_volume = reader.read();
@@ -78,10 +138,10 @@ impl MixerSettings {
             AnalogInputSettings::Stereo(analog_input_l)
         } else {
             let analog_input_r = InputMixerSettings {
-                volume: analog_input_volume.0,
-                chorus: analog_input_chorus.0,
-                delay: analog_input_delay.0,
-                reverb: analog_input_reverb.0,
+                volume: analog_input_volume.1,
+                chorus: analog_input_chorus.1,
+                delay: analog_input_delay.1,
+                reverb: analog_input_reverb.1,
             };
             AnalogInputSettings::DualMono((analog_input_l, analog_input_r))
         };
@@ -111,9 +171,62 @@ impl MixerSettings {
             dj_filter_type,
         })
     }
+
+    /// Stereo analog input leaves the right channel's send bytes as they are.
+    pub(crate) fn write(&self, w: &mut Writer) {
+        w.write(self.master_volume);
+        w.write(self.master_limit);
+        w.write_bytes(&self.track_volume);
+        w.write(self.chorus_volume);
+        w.write(self.delay_volume);
+        w.write(self.reverb_volume);
+
+        let (l, r) = match &self.analog_input {
+            AnalogInputSettings::Stereo(l) => (l, None),
+            AnalogInputSettings::DualMono((l, r)) => (l, Some(r)),
+        };
+        let pair = |w: &mut Writer, l: u8, r: Option<u8>| {
+            w.write(l);
+            match r {
+                Some(r) => w.write(r),
+                None => w.skip(1),
+            }
+        };
+        w.write(l.volume);
+        w.write(r.map_or(255, |r| r.volume));
+        w.write(self.usb_input.volume);
+        pair(w, l.chorus, r.map(|r| r.chorus));
+        pair(w, l.delay, r.map(|r| r.delay));
+        pair(w, l.reverb, r.map(|r| r.reverb));
+        w.write(self.usb_input.chorus);
+        w.write(self.usb_input.delay);
+        w.write(self.usb_input.reverb);
+
+        w.write(self.dj_filter);
+        w.write(self.dj_peak);
+        w.write(self.dj_filter_type);
+        w.skip(4);
+    }
+
+    pub fn track_volume(&self, track: usize) -> Option<u8> {
+        self.track_volume.get(track).copied()
+    }
+
+    pub fn set_track_volume(
+        &mut self,
+        track: usize,
+        volume: u8,
+    ) -> core::result::Result<(), String> {
+        let v = self
+            .track_volume
+            .get_mut(track)
+            .ok_or_else(|| format!("Track {} is out of range", track + 1))?;
+        *v = volume;
+        Ok(())
+    }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default, Hash)]
 pub struct InputMixerSettings {
     pub volume: u8,
     pub chorus: u8,
@@ -121,13 +234,18 @@ pub struct InputMixerSettings {
     pub reverb: u8,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub enum AnalogInputSettings {
     Stereo(InputMixerSettings),
     DualMono((InputMixerSettings, InputMixerSettings)),
 }
 
-#[derive(PartialEq, Debug, Clone)]
+/// The chorus, delay and reverb pages of the effects screen. Fields are in
+/// file order and named after the device controls, e.g. `delay_time_l` is the
+/// delay's TIME L. The three bytes after `chorus_reverb_send` and the one after
+/// `delay_reverb_send` aren't used by firmware 2.x or 3.x, and are left as they
+/// are when writing.
+#[derive(PartialEq, Debug, Clone, Hash)]
 pub struct EffectsSettings {
     pub chorus_mod_depth: u8,
     pub chorus_mod_freq: u8,
@@ -149,6 +267,31 @@ pub struct EffectsSettings {
     pub reverb_mod_freq: u8,
     pub reverb_width: u8,
 }
+impl Default for EffectsSettings {
+    fn default() -> Self {
+        Self {
+            chorus_mod_depth: 0x40,
+            chorus_mod_freq: 0x80,
+            chorus_reverb_send: 0xFF,
+
+            delay_hp: 0x36,
+            delay_lp: 0x40,
+            delay_time_l: 0xFF,
+            delay_time_r: 0x30,
+            delay_feedback: 0x30,
+            delay_width: 0x80,
+            delay_reverb_send: 0xFF,
+
+            reverb_hp: 0xF6,
+            reverb_lp: 0x10,
+            reverb_size: 0xE0,
+            reverb_damping: 0xFF,
+            reverb_mod_depth: 0xC0,
+            reverb_mod_freq: 0x10,
+            reverb_width: 0xFF,
+        }
+    }
+}
 impl EffectsSettings {
     pub(crate) fn from_reader(reader: &Reader) -> Result<Self> {
         let chorus_mod_depth = reader.read();
@@ -195,9 +338,33 @@ impl EffectsSettings {
             reverb_width,
         })
     }
+
+    pub(crate) fn write(&self, w: &mut Writer) {
+        w.write(self.chorus_mod_depth);
+        w.write(self.chorus_mod_freq);
+        w.write(self.chorus_reverb_send);
+        w.skip(3); //unused
+
+        w.write(self.delay_hp);
+        w.write(self.delay_lp);
+        w.write(self.delay_time_l);
+        w.write(self.delay_time_r);
+        w.write(self.delay_feedback);
+        w.write(self.delay_width);
+        w.write(self.delay_reverb_send);
+        w.skip(1); //unused
+
+        w.write(self.reverb_hp);
+        w.write(self.reverb_lp);
+        w.write(self.reverb_size);
+        w.write(self.reverb_damping);
+        w.write(self.reverb_mod_depth);
+        w.write(self.reverb_mod_freq);
+        w.write(self.reverb_width);
+    }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default, Hash)]
 pub struct MidiMapping {
     pub channel: u8,
     pub control_number: u8,
@@ -220,6 +387,18 @@ impl MidiMapping {
         })
     }
 
+    pub(crate) fn write(&self, w: &mut Writer) {
+        w.write_bytes(&[
+            self.channel,
+            self.control_number,
+            self.value,
+            self.typ,
+            self.param_index,
+            self.min_value,
+            self.max_value,
+        ]);
+    }
+
     pub fn empty(&self) -> bool {
         self.channel == 0
     }
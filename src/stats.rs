@@ -0,0 +1,103 @@
+use crate::*;
+
+use alloc::vec::Vec;
+
+/// Counts of a song's slots in use. See [`Song::stats`].
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct SongStats {
+    /// Slots holding an instrument.
+    pub used_instruments: usize,
+    /// Non-empty chains.
+    pub used_chains: usize,
+    /// Non-empty phrases.
+    pub used_phrases: usize,
+    /// Non-empty tables.
+    pub used_tables: usize,
+    /// Scales referenced by an `SCA` or `SCG` command.
+    pub used_scales: usize,
+    /// Grooves referenced by a `GRV` command.
+    pub used_grooves: usize,
+    /// How many instruments there are of each kind, in the order of
+    /// [`InstrumentKind`], leaving out kinds with none.
+    pub instrument_kind_counts: Vec<(InstrumentKind, usize)>,
+}
+
+impl fmt::Display for SongStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} instruments, {} chains, {} phrases, {} tables, {} scales, {} grooves",
+            self.used_instruments,
+            self.used_chains,
+            self.used_phrases,
+            self.used_tables,
+            self.used_scales,
+            self.used_grooves
+        )?;
+        for (i, (kind, n)) in self.instrument_kind_counts.iter().enumerate() {
+            let sep = if i == 0 { " (" } else { ", " };
+            write!(f, "{}{} {:?}", sep, n, kind)?;
+        }
+        if !self.instrument_kind_counts.is_empty() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl Song {
+    /// A summary of which slots are in use, in one pass over the song.
+    pub fn stats(&self) -> SongStats {
+        let mut scales = [false; Self::N_SCALES];
+        let mut grooves = [false; Self::N_GROOVES];
+        let fx = self
+            .phrases
+            .iter()
+            .flat_map(|p| p.steps.iter().flat_map(|s| s.fx_iter()))
+            .chain(
+                self.tables
+                    .iter()
+                    .flat_map(|t| t.steps.iter().flat_map(|s| s.fx_iter())),
+            );
+        for fx in fx {
+            let used = match fx.referenced_slot(self.version) {
+                Some((RefKind::Scale, s)) => scales.get_mut(s as usize),
+                Some((RefKind::Groove, g)) => grooves.get_mut(g as usize),
+                _ => None,
+            };
+            if let Some(used) = used {
+                *used = true;
+            }
+        }
+
+        use InstrumentKind::*;
+        let instrument_kind_counts = [
+            WavSynth, MacroSynth, Sampler, MIDIOut, FMSynth, HyperSynth, External,
+        ]
+        .into_iter()
+        .map(|kind| {
+            let n = self
+                .instruments
+                .iter()
+                .filter(|i| i.kind() == Some(kind))
+                .count();
+            (kind, n)
+        })
+        .filter(|&(_, n)| n > 0)
+        .collect();
+
+        SongStats {
+            used_instruments: self
+                .instruments
+                .iter()
+                .filter(|i| **i != Instrument::None)
+                .count(),
+            used_chains: self.chains.iter().filter(|c| !c.is_empty()).count(),
+            used_phrases: self.phrases.iter().filter(|p| !p.is_empty()).count(),
+            used_tables: self.tables.iter().filter(|t| !t.is_empty()).count(),
+            used_scales: scales.iter().filter(|&&u| u).count(),
+            used_grooves: grooves.iter().filter(|&&u| u).count(),
+            instrument_kind_counts,
+        }
+    }
+}
@@ -1,6 +1,12 @@
 use crate::reader::*;
 use crate::version::*;
 
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// The colours of a `.m8t` theme file. Songs don't carry a theme: it's a
+/// setting of the device rather than of the song, and no part of the song file
+/// layout (see [`crate::layout`]) holds one. This is the only way to read a theme.
 #[derive(PartialEq, Debug, Clone)]
 pub struct Theme {
     pub background: RGB,
@@ -18,11 +24,16 @@ pub struct Theme {
     pub meter_peak: RGB,
 }
 impl Theme {
-    const SIZE: usize = 39;
+    pub(crate) const SIZE: usize = 39;
+
+    #[cfg(feature = "std")]
+    pub fn read(reader: &mut impl std::io::Read) -> M8Result<Self> {
+        let buf = read_to_vec(reader)?;
+        Ok(Self::from_bytes(buf)?)
+    }
 
-    pub fn read(reader: &mut impl std::io::Read) -> Result<Self> {
-        let mut buf: Vec<u8> = vec![];
-        reader.read_to_end(&mut buf).unwrap();
+    /// Parse the full contents of a file that's already in memory.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self> {
         let len = buf.len();
         let reader = Reader::new(buf);
 
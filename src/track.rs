@@ -0,0 +1,64 @@
+use crate::*;
+
+/// One of the song's eight tracks: a column of the song screen, with the chains
+/// it plays resolved. See [`Song::track`].
+#[derive(Clone, Copy)]
+pub struct TrackView<'a> {
+    song: &'a Song,
+    track: usize,
+}
+
+impl<'a> TrackView<'a> {
+    pub const N_TRACKS: usize = limits::N_TRACKS;
+
+    /// Numbered from 0.
+    pub fn number(&self) -> usize {
+        self.track
+    }
+
+    /// The chain number on each song row of the track, skipping empty rows.
+    pub fn chain_numbers(&self) -> impl Iterator<Item = (usize, u8)> + 'a {
+        let track = self.track;
+        self.song
+            .song
+            .steps
+            .chunks(Self::N_TRACKS)
+            .enumerate()
+            .map(move |(row, steps)| (row, steps[track]))
+            .filter(|&(_, chain)| chain != 255)
+    }
+
+    /// The chain on each song row of the track, skipping empty rows.
+    pub fn chains(&self) -> impl Iterator<Item = (usize, &'a Chain)> + 'a {
+        let song = self.song;
+        self.chain_numbers()
+            .filter_map(move |(row, chain)| Some((row, song.chains.get(chain as usize)?)))
+    }
+
+    /// Every phrase the track plays, in order, with the song row and chain step
+    /// it's played from. Empty chain steps are skipped. The chain step holds the
+    /// transpose applied to the phrase.
+    pub fn phrases(&self) -> impl Iterator<Item = (usize, &'a ChainStep, &'a Phrase)> + 'a {
+        let song = self.song;
+        self.chains().flat_map(move |(row, chain)| {
+            chain
+                .steps
+                .iter()
+                .filter_map(move |step| Some((row, step, song.phrases.get(step.phrase as usize)?)))
+        })
+    }
+}
+
+impl Song {
+    /// A view of track `t` (0-7), or `None` if it's out of range.
+    pub fn track(&self, t: usize) -> Option<TrackView<'_>> {
+        (t < TrackView::N_TRACKS).then_some(TrackView {
+            song: self,
+            track: t,
+        })
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = TrackView<'_>> {
+        (0..TrackView::N_TRACKS).map(move |track| TrackView { song: self, track })
+    }
+}
@@ -1,8 +1,9 @@
 use crate::reader::*;
+use crate::writer::*;
 
-use std::fmt;
+use core::fmt;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct Version {
     pub major: u8,
     pub minor: u8,
@@ -11,11 +12,7 @@ pub struct Version {
 
 impl Default for Version {
     fn default() -> Self {
-        Self {
-            major: 3,
-            minor: 0,
-            patch: 0,
-        }
+        Self::V3_0
     }
 }
 
@@ -34,6 +31,19 @@ impl fmt::Debug for Version {
 impl Version {
     pub(crate) const SIZE: usize = 14;
 
+    /// The first firmware to store scales in songs.
+    pub const V2_5: Self = Self::new(2, 5, 0);
+    /// The first firmware with the 3.x instrument layout.
+    pub const V3_0: Self = Self::new(3, 0, 0);
+
+    pub const fn new(major: u8, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
     pub(crate) fn from_reader(reader: &Reader) -> Result<Self> {
         let _version_string = reader.read_bytes(10);
         let lsb = reader.read();
@@ -50,6 +60,33 @@ impl Version {
         })
     }
 
+    /// Write the full header, in the order `from_reader` reads it.
+    pub(crate) fn write(&self, w: &mut Writer) {
+        w.write_bytes(b"M8VERSION\0");
+        w.write((self.minor << 4) | (self.patch & 0x0F));
+        w.write(self.major & 0x0F);
+        w.write_bytes(&[0, 0]);
+    }
+
+    /// Whether songs from this firmware can be parsed: 1.x to 3.x. 1.x songs
+    /// share the 2.x layout.
+    pub fn is_supported(&self) -> bool {
+        matches!(self.major, 1..=3)
+    }
+
+    /// `self` if supported, otherwise the version whose layout is closest: 3.0 for
+    /// newer firmware, 1.0 for older.
+    pub fn closest_supported(&self) -> Self {
+        if self.is_supported() {
+            *self
+        } else if self.major > 3 {
+            Self::V3_0
+        } else {
+            Self::new(1, 0, 0)
+        }
+    }
+
+    /// Same as `*self >= Version::new(major, minor, 0)`.
     pub fn at_least(&self, major: u8, minor: u8) -> bool {
         self.major > major || (self.major == major && self.minor >= minor)
     }
@@ -0,0 +1,133 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The counterpart to `Reader`: writes M8 data over an existing buffer.
+///
+/// Bytes that are never written keep their original value, so writing a parsed
+/// file back over its own bytes preserves anything this crate doesn't parse.
+/// Writing past the end of the buffer grows it, zero-filling any gap.
+pub struct Writer {
+    buffer: Vec<u8>,
+    position: usize,
+    written: usize,
+}
+
+impl Writer {
+    pub fn new(buffer: Vec<u8>) -> Self {
+        Self {
+            buffer,
+            position: 0,
+            written: 0,
+        }
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    /// The buffer as written so far.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Like `finish`, but fails if the bytes written didn't reach `expected`, or if
+    /// the buffer isn't exactly `expected` bytes long.
+    pub fn finish_checked(self, expected: usize) -> Result<Vec<u8>, String> {
+        if self.written < expected {
+            return Err(format!(
+                "Write stopped at {:#x}, expected {:#x}",
+                self.written, expected
+            ));
+        }
+        if self.buffer.len() != expected {
+            return Err(format!(
+                "Buffer is {:#x} bytes, expected {:#x}",
+                self.buffer.len(),
+                expected
+            ));
+        }
+        Ok(self.buffer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// The furthest position written to so far.
+    pub fn written_len(&self) -> usize {
+        self.written
+    }
+
+    pub fn pos(&self) -> usize {
+        self.position
+    }
+
+    /// Move to the absolute position `pos`, leaving the skipped bytes as they are.
+    pub fn seek(&mut self, pos: usize) {
+        self.position = pos;
+    }
+
+    /// Move forward `n` bytes, leaving the skipped bytes as they are.
+    pub fn skip(&mut self, n: usize) {
+        self.position += n;
+    }
+
+    pub fn write(&mut self, v: u8) {
+        self.write_bytes(&[v]);
+    }
+
+    pub fn write_bytes(&mut self, bs: &[u8]) {
+        let end = self.position + bs.len();
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0);
+        }
+        self.buffer[self.position..end].copy_from_slice(bs);
+        self.position = end;
+        self.written = self.written.max(end);
+    }
+
+    pub fn write_bool(&mut self, v: bool) {
+        self.write(v as u8);
+    }
+
+    /// Write `s` into a field of `n` bytes, truncating it if needed. A shorter
+    /// string is terminated with a NUL, unless the buffer already holds a
+    /// terminator (NUL or 0xFF) there. The rest of the field is left as it is, or
+    /// zero-filled if it's past the end of the buffer.
+    pub fn write_string(&mut self, s: &str, n: usize) {
+        let end = self.position + n;
+        let bs = &s.as_bytes()[..s.len().min(n)];
+        self.write_bytes(bs);
+        if bs.len() < n && !matches!(self.buffer.get(self.position), Some(0 | 0xFF)) {
+            self.write(0);
+        }
+        if self.buffer.len() < end {
+            self.buffer.resize(end, 0);
+        }
+        self.position = end;
+        self.written = self.written.max(end);
+    }
+
+    /// Write `count` copies of `v`.
+    pub fn fill(&mut self, v: u8, count: usize) {
+        self.write_bytes(&vec![v; count]);
+    }
+
+    /// Write `v` up to the absolute position `pos`. Does nothing if already past it.
+    pub fn fill_till(&mut self, v: u8, pos: usize) {
+        if pos > self.position {
+            self.fill(v, pos - self.position);
+        }
+    }
+
+    /// Like `seek`, but zero-fills the bytes between here and `pos`.
+    pub fn pad_to(&mut self, pos: usize) {
+        self.fill_till(0, pos);
+    }
+}